@@ -0,0 +1,107 @@
+#![warn(clippy::all, rust_2018_idioms)]
+
+//! Compares the move generators and flip routines of `BitBoard`, and
+//! measures random playouts per second:
+//!
+//! `cargo bench --bench playout`
+//! `cargo bench --bench playout --features simd`
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use othello::othello::moai::BitBoard;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const ROUNDS: usize = 200;
+
+/// Positions and moves from random games, so every benchmark sees the
+/// same inputs.
+fn sample_moves() -> Vec<(BitBoard, u64)> {
+    let mut rng = StdRng::seed_from_u64(31);
+    let mut samples = Vec::new();
+    for _ in 0..100 {
+        let mut board = BitBoard::initial();
+        while !board.is_game_ended() {
+            let moves = board.moves();
+            if moves.is_empty() {
+                board = board.play(0);
+                continue;
+            }
+            let position = moves[rng.gen_range(0..moves.len())];
+            samples.push((board, position));
+            board = board.play(position);
+        }
+    }
+
+    samples
+}
+
+/// Runs `f` over the samples `ROUNDS` times and prints ns per call.
+fn bench(name: &str, samples: &[(BitBoard, u64)], f: impl Fn(&BitBoard, u64, u64, u64) -> u64) {
+    let start = Instant::now();
+    let mut acc = 0;
+    for _ in 0..ROUNDS {
+        for (board, position) in samples.iter() {
+            let (p, o) = board.curr_board();
+            acc ^= f(board, p, o, black_box(*position));
+        }
+    }
+    black_box(acc);
+    let ns = start.elapsed().as_nanos() as f64 / (ROUNDS * samples.len()) as f64;
+    println!("{:<24} {:>8.2} ns", name, ns);
+}
+
+/// Plays random games to the end with the given flip routine, working on
+/// (player, opponent) pairs like `BitBoard::playout`.
+fn playouts(name: &str, seconds: f64, update: impl Fn(&BitBoard, u64, u64, u64) -> (u64, u64)) {
+    let board = BitBoard::initial();
+    let mut rng = StdRng::seed_from_u64(31);
+    let start = Instant::now();
+    let mut count = 0u64;
+    while start.elapsed().as_secs_f64() < seconds {
+        let (mut p, mut o) = board.curr_board();
+        let mut passes = 0;
+        while passes < 2 {
+            let legal = board.legal_move_bits(p, o);
+            if legal == 0 {
+                std::mem::swap(&mut p, &mut o);
+                passes += 1;
+                continue;
+            }
+            passes = 0;
+            let mut bits = legal;
+            for _ in 0..rng.gen_range(0..legal.count_ones()) {
+                bits &= bits - 1;
+            }
+            let (next_p, next_o) = update(&board, p, o, bits & bits.wrapping_neg());
+            p = next_o;
+            o = next_p;
+        }
+        black_box((p, o));
+        count += 1;
+    }
+    let rate = count as f64 / start.elapsed().as_secs_f64();
+    println!("{:<24} {:>8.0} playouts/s", name, rate);
+}
+
+fn main() {
+    let samples = sample_moves();
+    println!("{} sample positions", samples.len());
+
+    bench("update_by_transfer", &samples, |b, p, o, m| {
+        b.update_by_transfer(p, o, m).0
+    });
+    bench("update", &samples, |b, p, o, m| b.update(p, o, m).0);
+    bench("legal_move_bits_scalar", &samples, |b, p, o, _| {
+        b.legal_move_bits_scalar(p, o)
+    });
+    bench("legal_move_bits", &samples, |b, p, o, _| {
+        b.legal_move_bits(p, o)
+    });
+
+    playouts("playouts (transfer)", 2.0, |b, p, o, m| {
+        b.update_by_transfer(p, o, m)
+    });
+    playouts("playouts", 2.0, |b, p, o, m| b.update(p, o, m));
+}
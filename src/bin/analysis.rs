@@ -0,0 +1,155 @@
+#![warn(clippy::all, rust_2018_idioms)]
+
+//! An HTTP service answering analysis requests in JSON:
+//!
+//! `cargo run --release --bin analysis -- [options]`
+//!
+//! See `othello::othello::analysis` for the endpoints, e.g.
+//! `curl -d '{"moves":["f5"],"time_ms":500}' localhost:8080/analyze`.
+
+use std::io::Read;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use othello::othello::analysis::{self, Config};
+use tiny_http::{Header, Request, Response, Server};
+
+const USAGE: &str = "\
+usage: analysis [options]
+  --port <port>         port to listen on (default 8080)
+  --threads <n>         requests handled at once (default: number of CPUs)
+  --queue <n>           requests waiting beyond those, others get 503 (default 16)
+  --max-time <ms>       longest search a request may ask for (default 10000)
+  --max-iterations <n>  most simulations a request may ask for (default 10000000)
+  --max-playout <n>     most playouts per MCTS simulation (default 100)
+  --max-nodes <n>       most positions /solve may search (default 100000000)
+  --max-body <bytes>    largest request body (default 65536)";
+
+struct Options {
+    port: u16,
+    threads: usize,
+    queue: usize,
+    max_body: usize,
+    config: Config,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        port: 8080,
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        queue: 16,
+        max_body: 65536,
+        config: Config::default(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {}", arg))?;
+        let number = || {
+            value
+                .parse::<u64>()
+                .map_err(|_| format!("bad value for {}: {}", arg, value))
+        };
+        match arg.as_str() {
+            "--port" => options.port = number()?.min(u16::MAX as u64) as u16,
+            "--threads" => options.threads = number()?.max(1) as usize,
+            "--queue" => options.queue = number()? as usize,
+            "--max-time" => options.config.max_time = number()? as u128,
+            "--max-iterations" => {
+                options.config.max_iterations = number()?.min(i32::MAX as u64) as i32
+            }
+            "--max-playout" => options.config.max_playout = number()?.min(i32::MAX as u64) as i32,
+            "--max-nodes" => options.config.max_nodes = number()?,
+            "--max-body" => options.max_body = number()? as usize,
+            _ => return Err(format!("unknown option: {}", arg)),
+        }
+    }
+
+    Ok(options)
+}
+
+fn respond(request: Request, (status, body): analysis::Response) {
+    let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header);
+    if let Err(err) = request.respond(response) {
+        eprintln!("{}", err);
+    }
+}
+
+fn serve(mut request: Request, options: &Options) {
+    let too_large = (413, r#"{"error":"request too large"}"#.to_owned());
+    if request
+        .body_length()
+        .is_some_and(|len| len > options.max_body)
+    {
+        return respond(request, too_large);
+    }
+    let mut body = String::new();
+    let read = request
+        .as_reader()
+        .take(options.max_body as u64 + 1)
+        .read_to_string(&mut body);
+    let response = match read {
+        Err(_) => (400, r#"{"error":"bad body"}"#.to_owned()),
+        Ok(_) if body.len() > options.max_body => too_large,
+        Ok(_) => {
+            let method = request.method().to_string();
+            analysis::handle(&options.config, &method, request.url(), &body)
+        }
+    };
+    respond(request, response);
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => Arc::new(options),
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let server = match Server::http(("0.0.0.0", options.port)) {
+        Ok(server) => server,
+        Err(err) => {
+            eprintln!("cannot listen on port {}: {}", options.port, err);
+            std::process::exit(1);
+        }
+    };
+    println!(
+        "listening on port {}, {} threads",
+        options.port, options.threads
+    );
+
+    // Requests accepted but not answered yet.
+    let pending = Arc::new(AtomicUsize::new(0));
+    let (sender, receiver) = mpsc::channel::<Request>();
+    let receiver = Arc::new(Mutex::new(receiver));
+    for _ in 0..options.threads {
+        let (options, pending, receiver) = (options.clone(), pending.clone(), receiver.clone());
+        thread::spawn(move || loop {
+            let request = match receiver.lock().unwrap().recv() {
+                Ok(request) => request,
+                Err(_) => return,
+            };
+            serve(request, &options);
+            pending.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+
+    for request in server.incoming_requests() {
+        if pending.load(Ordering::SeqCst) >= options.threads + options.queue {
+            respond(request, (503, r#"{"error":"busy"}"#.to_owned()));
+            continue;
+        }
+        pending.fetch_add(1, Ordering::SeqCst);
+        if sender.send(request).is_err() {
+            break;
+        }
+    }
+}
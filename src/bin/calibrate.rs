@@ -0,0 +1,44 @@
+#![warn(clippy::all, rust_2018_idioms)]
+
+//! Plays each difficulty level against the one below it and reports the
+//! approximate Elo gaps:
+//!
+//! `cargo run --release --bin calibrate -- [games]`
+
+use othello::othello::difficulty::{self, Difficulty};
+
+fn main() {
+    let games = std::env::args()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(20);
+
+    println!("{} games per pair", games);
+    let results = difficulty::calibrate(games, |c| {
+        println!(
+            "{:<12} vs {:<12} +{} ={} -{} ({:.1}%, {:+.0} Elo)",
+            c.stronger.name(),
+            c.weaker.name(),
+            c.score.wins,
+            c.score.draws,
+            c.score.losses,
+            c.score.ratio() * 100.0,
+            c.elo()
+        );
+    });
+
+    let mut total = 0.0;
+    println!("{:<12} {:>6}", "level", "Elo");
+    println!("{:<12} {:>6.0}", Difficulty::ALL[0].name(), total);
+    for c in results.iter() {
+        total += c.elo();
+        println!("{:<12} {:>6.0}", c.stronger.name(), total);
+    }
+
+    if results.iter().all(|c| c.is_monotonic()) {
+        println!("ladder is monotonic");
+    } else {
+        println!("ladder is NOT monotonic");
+        std::process::exit(1);
+    }
+}
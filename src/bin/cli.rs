@@ -0,0 +1,293 @@
+#![warn(clippy::all, rust_2018_idioms)]
+
+//! Plays and analyses games in a terminal, without a display:
+//!
+//! `cargo run --release --bin cli -- [engine spec]`
+//!
+//! Type `help` for the commands. Moves are entered as `f5`.
+
+use std::cmp::Reverse;
+use std::io::{self, BufRead, Write};
+
+use othello::othello::engine::{Engine, EngineKind, Limits, SearchInfo};
+use othello::othello::game::{self, parse_square, player_name, square_name, Game};
+use othello::othello::ggf::{self, GgfGame};
+
+const HELP: &str = "\
+f5              play a move
+new             start a new game
+undo            take back your last move (and the computer's reply)
+setboard <pos>  set up a position: 64 squares of X/*, O and -, then the side to move
+go [ms]         let the computer play a move now
+analyze [ms]    show the computer's opinion of every legal move
+hint            suggest a move
+computer <black|white|off>
+                choose the side the computer plays after your moves
+engine <spec>   change the engine, e.g. `mcts:cp=1.5` or `greedy`
+load <file>     load a GGF game
+save <file>     save the game as GGF
+board           show the board
+quit            exit";
+
+/// Milliseconds per move when none is given.
+const DEFAULT_TIME: u128 = 1000;
+
+struct Repl {
+    engine: Box<dyn Engine>,
+    game: Game,
+    /// Side played by the computer, if any.
+    computer: Option<i32>,
+}
+
+impl Repl {
+    fn show_board(&self) {
+        let board = self.game.position();
+        let (black, white) = board.count();
+        print!("{}", game::board_text(&board, true));
+        if board.is_game_ended() {
+            let result = match board.winner() {
+                2 => "draw".to_owned(),
+                winner => format!("{} wins", player_name(winner)),
+            };
+            println!("game over: {}-{}, {}", black, white, result);
+        } else {
+            println!(
+                "X {} O {}, {} to move",
+                black,
+                white,
+                player_name(board.player())
+            );
+        }
+    }
+
+    fn search(&mut self, time: u128) -> Option<SearchInfo> {
+        let mut last = None;
+        self.engine.set_position(self.game.position());
+        match self
+            .engine
+            .think(Limits::time(time), &mut |info| last = Some(info.clone()))
+        {
+            Ok(_) => last,
+            Err(err) => {
+                println!("{}: {}", self.engine.name(), err);
+                None
+            }
+        }
+    }
+
+    /// Plays `position` and then any passes that follow.
+    fn play(&mut self, position: u64) -> Result<(), String> {
+        self.game.play(position)?;
+        loop {
+            let board = self.game.position();
+            if !game::is_legal(&board, 0) {
+                break;
+            }
+            println!("{} passes", player_name(board.player()));
+            self.game.play(0)?;
+        }
+
+        Ok(())
+    }
+
+    /// Lets the computer move; returns false if the game is over.
+    fn computer_move(&mut self, time: u128) -> bool {
+        if self.game.position().is_game_ended() {
+            return false;
+        }
+        let position = match self.search(time) {
+            Some(info) => info.best_move,
+            None => self.game.position().moves()[0],
+        };
+        println!("{} plays {}", self.engine.name(), square_name(position));
+        self.play(position).is_ok()
+    }
+
+    fn reply(&mut self) {
+        while !self.game.position().is_game_ended()
+            && self.computer == Some(self.game.position().player())
+        {
+            self.computer_move(DEFAULT_TIME);
+        }
+        self.show_board();
+    }
+
+    fn analyze(&mut self, time: u128) {
+        if self.game.position().legal_bits() == 0 {
+            println!("no legal moves");
+            return;
+        }
+        let mut info = match self.search(time) {
+            Some(info) => info,
+            None => return,
+        };
+        info.moves.sort_by_key(|stat| Reverse(stat.visits));
+        println!("{} simulations in {} ms", info.iterations, info.elapsed);
+        for stat in info.moves.iter() {
+            println!(
+                "{}  {:>5.1}%  {:>7} visits",
+                square_name(stat.position),
+                (stat.score + 1.0) * 50.0,
+                stat.visits
+            );
+        }
+        let pv: Vec<String> = info.pv.iter().map(|&m| square_name(m)).collect();
+        println!("pv: {}", pv.join(" "));
+    }
+
+    /// Takes back moves until it is a human's turn again.
+    fn undo(&mut self) {
+        if self.game.undo().is_none() {
+            println!("nothing to undo");
+            return;
+        }
+        while !self.game.moves.is_empty() {
+            let board = self.game.position();
+            if self.computer != Some(board.player()) && board.legal_bits() != 0 {
+                break;
+            }
+            self.game.undo();
+        }
+        self.show_board();
+    }
+
+    fn set_board(&mut self, text: &str) -> Result<(), String> {
+        let text = text.trim();
+        let board = if text.starts_with('8') {
+            ggf::parse_board(text)?
+        } else {
+            ggf::parse_board(&format!("8 {}", text))?
+        };
+        self.game = Game::new(board);
+        self.engine.new_game();
+        Ok(())
+    }
+
+    fn load(&mut self, path: &str) -> Result<(), String> {
+        let text = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        self.game = GgfGame::parse(&text)?.game;
+        self.engine.new_game();
+        Ok(())
+    }
+
+    fn save(&self, path: &str) -> Result<(), String> {
+        let (black, white) = match self.computer {
+            Some(0) => (self.engine.name(), "human".to_owned()),
+            Some(_) => ("human".to_owned(), self.engine.name()),
+            None => ("human".to_owned(), "human".to_owned()),
+        };
+        let ggf = GgfGame::new(self.game.clone(), &black, &white);
+        std::fs::write(path, format!("{}\n", ggf)).map_err(|err| err.to_string())
+    }
+
+    /// Runs one command; returns false to quit.
+    fn handle(&mut self, line: &str) -> bool {
+        let mut parts = line.trim().splitn(2, ' ');
+        let command = parts.next().unwrap_or("");
+        let args = parts.next().unwrap_or("").trim();
+        let time = args.parse().unwrap_or(DEFAULT_TIME);
+        let result = match command {
+            "" => Ok(()),
+            "quit" | "exit" => return false,
+            "help" => {
+                println!("{}", HELP);
+                Ok(())
+            }
+            "board" => {
+                self.show_board();
+                Ok(())
+            }
+            "new" => {
+                self.game = Game::default();
+                self.engine.new_game();
+                self.reply();
+                Ok(())
+            }
+            "undo" => {
+                self.undo();
+                Ok(())
+            }
+            "setboard" => self.set_board(args).map(|_| self.show_board()),
+            "go" => {
+                if self.computer_move(time) {
+                    self.reply();
+                } else {
+                    println!("the game is over");
+                }
+                Ok(())
+            }
+            "analyze" => {
+                self.analyze(time);
+                Ok(())
+            }
+            "hint" => {
+                match self.search(DEFAULT_TIME) {
+                    Some(info) if info.best_move != 0 => {
+                        println!("hint: {}", square_name(info.best_move))
+                    }
+                    _ => println!("no legal moves"),
+                }
+                Ok(())
+            }
+            "computer" => match args {
+                "black" | "white" => {
+                    self.computer = Some(if args == "black" { 0 } else { 1 });
+                    self.reply();
+                    Ok(())
+                }
+                "off" => {
+                    self.computer = None;
+                    Ok(())
+                }
+                _ => Err("usage: computer <black|white|off>".to_owned()),
+            },
+            "engine" => EngineKind::parse(args).map(|kind| {
+                self.engine = kind.create();
+                println!("engine: {}", self.engine.name());
+            }),
+            "load" => self.load(args).map(|_| self.show_board()),
+            "save" => self.save(args),
+            _ => match parse_square(command) {
+                Some(position) => self.play(position).map(|_| self.reply()),
+                None => Err(format!("unknown command: {} (try `help`)", command)),
+            },
+        };
+        if let Err(err) = result {
+            println!("{}", err);
+        }
+
+        true
+    }
+}
+
+fn main() -> io::Result<()> {
+    let kind = match std::env::args().nth(1) {
+        Some(spec) => match EngineKind::parse(&spec) {
+            Ok(kind) => kind,
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(2);
+            }
+        },
+        None => EngineKind::Mcts(Default::default()),
+    };
+    let mut repl = Repl {
+        engine: kind.create(),
+        game: Game::default(),
+        computer: Some(1),
+    };
+
+    println!("Type `help` for the commands.");
+    repl.show_board();
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 || !repl.handle(&line) {
+            break;
+        }
+    }
+
+    Ok(())
+}
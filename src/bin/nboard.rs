@@ -0,0 +1,215 @@
+#![warn(clippy::all, rust_2018_idioms)]
+
+//! Speaks the NBoard protocol on stdin/stdout, so that the engine can be
+//! used from NBoard and other GUIs supporting it:
+//!
+//! `cargo run --release --bin nboard -- [engine spec]`
+//!
+//! The spec is as for `EngineKind::parse`, e.g. `mcts:cp=1.5`. Commands not
+//! listed in `Session::handle` are ignored.
+
+use std::cmp::Reverse;
+use std::io::{self, BufRead, Write};
+
+use othello::othello::engine::{
+    Engine, EngineKind, Limits, SearchInfo, DISKS_PER_SCORE, ITERATIONS_PER_DEPTH,
+};
+use othello::othello::game::{parse_square, square_name, Game};
+use othello::othello::ggf::GgfGame;
+
+struct Session<W: Write> {
+    engine: Box<dyn Engine>,
+    game: Game,
+    depth: i32,
+    out: W,
+}
+
+impl<W: Write> Session<W> {
+    fn send(&mut self, message: &str) -> io::Result<()> {
+        writeln!(self.out, "{}", message)?;
+        self.out.flush()
+    }
+
+    fn limits(&self) -> Limits {
+        Limits::iterations(self.depth * ITERATIONS_PER_DEPTH)
+    }
+
+    /// Searches the current position and returns the final search info.
+    fn search(&mut self) -> Result<SearchInfo, String> {
+        let mut last = SearchInfo::default();
+        self.engine.set_position(self.game.position());
+        let limits = self.limits();
+        self.engine
+            .think(limits, &mut |info| last = info.clone())
+            .map_err(|err| err.to_string())?;
+
+        Ok(last)
+    }
+
+    fn handle(&mut self, line: &str) -> io::Result<()> {
+        let mut parts = line.trim().splitn(2, ' ');
+        let command = parts.next().unwrap_or("");
+        let args = parts.next().unwrap_or("").trim();
+        match command {
+            "nboard" => {
+                let name = format!("set myname {}", self.engine.name().replace(' ', "_"));
+                self.send(&name)?;
+            }
+            "set" => self.handle_set(args)?,
+            "move" => {
+                let name = args.split('/').next().unwrap_or("");
+                let result = parse_square(name)
+                    .ok_or_else(|| format!("bad move: {}", args))
+                    .and_then(|position| {
+                        // A move while the side to move has none implies a pass.
+                        let board = self.game.position();
+                        let player = if position != 0 && board.legal_bits() == 0 {
+                            1 - board.player()
+                        } else {
+                            board.player()
+                        };
+                        self.game.play_as(player, position)
+                    });
+                if let Err(err) = result {
+                    eprintln!("{}", err);
+                }
+            }
+            "go" => self.go()?,
+            "hint" => self.hint(args.parse().unwrap_or(1))?,
+            "ping" => {
+                let pong = format!("pong {}", args);
+                self.send(&pong)?;
+            }
+            "learn" => self.send("learned")?,
+            "" => {}
+            _ => eprintln!("unknown command: {}", line),
+        }
+
+        Ok(())
+    }
+
+    fn handle_set(&mut self, args: &str) -> io::Result<()> {
+        let mut parts = args.splitn(2, ' ');
+        let key = parts.next().unwrap_or("");
+        let value = parts.next().unwrap_or("").trim();
+        match key {
+            "game" => match GgfGame::parse(value) {
+                Ok(ggf) => {
+                    if ggf.game.start != self.game.start
+                        || !ggf.game.moves.starts_with(&self.game.moves)
+                    {
+                        self.engine.new_game();
+                    }
+                    self.game = ggf.game;
+                }
+                Err(err) => eprintln!("bad game: {}", err),
+            },
+            "depth" => match value.parse::<i32>() {
+                Ok(depth) => self.depth = depth.clamp(1, 60),
+                Err(_) => eprintln!("bad depth: {}", value),
+            },
+            // Centidisks; a positive contempt makes draws look like losses.
+            "contempt" => match value.parse::<f64>() {
+                Ok(contempt) => {
+                    let draw_score = -contempt / 100.0 / DISKS_PER_SCORE;
+                    self.engine.set_draw_score(draw_score.clamp(-1.0, 1.0));
+                }
+                Err(_) => eprintln!("bad contempt: {}", value),
+            },
+            _ => eprintln!("unknown setting: {}", key),
+        }
+
+        Ok(())
+    }
+
+    fn go(&mut self) -> io::Result<()> {
+        let board = self.game.position();
+        if board.legal_bits() == 0 {
+            return self.send("=== PA");
+        }
+
+        self.send("status thinking")?;
+        match self.search() {
+            Ok(info) => {
+                let seconds = info.elapsed as f64 / 1000.0;
+                let nodestats = format!("nodestats {} {:.3}", info.iterations, seconds);
+                self.send(&nodestats)?;
+                let reply = format!(
+                    "=== {}/{:.2}/{:.3}",
+                    square_name(info.best_move).to_ascii_uppercase(),
+                    info.score * DISKS_PER_SCORE,
+                    seconds
+                );
+                self.send(&reply)?;
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                let moves = board.moves();
+                let reply = format!("=== {}", square_name(moves[0]).to_ascii_uppercase());
+                self.send(&reply)?;
+            }
+        }
+        self.send("status")
+    }
+
+    /// Reports the `n` most searched moves, best first.
+    fn hint(&mut self, n: usize) -> io::Result<()> {
+        if self.game.position().legal_bits() == 0 {
+            return Ok(());
+        }
+
+        self.send("status thinking")?;
+        match self.search() {
+            Ok(mut info) => {
+                info.moves.sort_by_key(|stat| Reverse(stat.visits));
+                for stat in info.moves.iter().take(n) {
+                    let pv = if stat.position == info.best_move {
+                        info.pv.iter().map(|&m| square_name(m)).collect()
+                    } else {
+                        square_name(stat.position)
+                    };
+                    let search = format!(
+                        "search {} {:.2} 0 {}",
+                        pv.to_ascii_uppercase(),
+                        stat.score * DISKS_PER_SCORE,
+                        self.depth
+                    );
+                    self.send(&search)?;
+                }
+            }
+            Err(err) => eprintln!("{}", err),
+        }
+        self.send("status")
+    }
+}
+
+fn main() -> io::Result<()> {
+    let kind = match std::env::args().nth(1) {
+        Some(spec) => match EngineKind::parse(&spec) {
+            Ok(kind) => kind,
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(2);
+            }
+        },
+        None => EngineKind::Mcts(Default::default()),
+    };
+
+    let stdout = io::stdout();
+    let mut session = Session {
+        engine: kind.create(),
+        game: Game::default(),
+        depth: 12,
+        out: stdout.lock(),
+    };
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if line.trim() == "quit" {
+            break;
+        }
+        session.handle(&line)?;
+    }
+
+    Ok(())
+}
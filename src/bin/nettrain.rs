@@ -0,0 +1,116 @@
+#![warn(clippy::all, rust_2018_idioms)]
+
+//! Trains a policy/value network by self-play, on the CPU:
+//!
+//! `cargo run --release --bin nettrain -- [options] --out <network>`
+//!
+//! The network is written after every generation and is played with the
+//! `network:<file>` engine. See `othello::othello::network`.
+
+use othello::othello::engine::Limits;
+use othello::othello::network::training::Training;
+use othello::othello::network::{Network, DEFAULT_HIDDEN};
+
+const USAGE: &str = "\
+usage: nettrain [options] --out <network>
+  --init <network>          continue training this network instead of a new one
+  --hidden <n>              units per hidden layer of a new network (default 128)
+  --generations <n>         self-play then training rounds (default 10)
+  --games <n>               self-play games per generation (default 100)
+  --iterations <n>          simulations per move (default 200)
+  --threads <n>             games played at once (default: number of CPUs)
+  --window <n>              most recent positions trained on (default 100000)
+  --epochs <n>              passes over them per generation (default 2)
+  --batch <n>               positions per training step (default 64)
+  --rate <r>                learning rate (default 0.01)
+  --temperature-plies <n>   moves drawn in proportion to the visits in each game (default 20)
+  --seed <n>                seed of the random choices (default 0)";
+
+struct Options {
+    training: Training,
+    hidden: usize,
+    init: Option<String>,
+    out: String,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut training = Training {
+        threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        ..Default::default()
+    };
+    let (mut hidden, mut init, mut out) = (DEFAULT_HIDDEN, None, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {}", arg))?;
+        let bad = || format!("bad value for {}: {}", arg, value);
+        let number = || value.parse::<u64>().map_err(|_| bad());
+        match arg.as_str() {
+            "--init" => init = Some(value.clone()),
+            "--out" => out = Some(value.clone()),
+            "--hidden" => hidden = number()?.clamp(1, 4096) as usize,
+            "--generations" => training.generations = number()? as u32,
+            "--games" => training.games = number()? as u32,
+            "--iterations" => {
+                training.limits = Limits::iterations(number()?.clamp(1, i32::MAX as u64) as i32)
+            }
+            "--threads" => training.threads = number()?.max(1) as usize,
+            "--window" => training.window = number()? as usize,
+            "--epochs" => training.epochs = number()? as u32,
+            "--batch" => training.batch = number()?.max(1) as usize,
+            "--rate" => training.rate = value.parse().map_err(|_| bad())?,
+            "--temperature-plies" => training.temperature_plies = number()? as usize,
+            "--seed" => training.seed = number()?,
+            _ => return Err(format!("unknown option: {}", arg)),
+        }
+    }
+
+    Ok(Options {
+        training,
+        hidden,
+        init,
+        out: out.ok_or("--out is needed")?,
+    })
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let mut network = match &options.init {
+        Some(path) => match Network::load(path) {
+            Ok(network) => network,
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                std::process::exit(1);
+            }
+        },
+        None => Network::new(options.hidden, options.training.seed),
+    };
+
+    let result = options
+        .training
+        .run(&mut network, &options.out, |report| {
+            println!(
+                "generation {}: black {} white {} draws {}, {} positions, value loss {:.3}, policy loss {:.3}",
+                report.generation,
+                report.wins[0],
+                report.wins[1],
+                report.wins[2],
+                report.positions,
+                report.loss.value,
+                report.loss.policy
+            );
+        });
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    println!("wrote {}", options.out);
+}
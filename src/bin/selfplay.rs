@@ -0,0 +1,154 @@
+#![warn(clippy::all, rust_2018_idioms)]
+
+//! Generates self-play training data, or summarises a file of it:
+//!
+//! `cargo run --release --bin selfplay -- [options] --out <file> [engine spec]`
+//! `cargo run --release --bin selfplay -- --read <file>`
+//!
+//! The format is described in `othello::othello::selfplay`.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+
+use othello::othello::engine::{EngineKind, Limits};
+use othello::othello::selfplay::{SampleReader, SampleWriter, SelfPlay};
+use othello::othello::tournament;
+
+const USAGE: &str = "\
+usage: selfplay [options] --out <file> [engine spec]
+       selfplay --read <file>
+  --games <n>               games to play (default 100)
+  --time <ms>               time per move
+  --iterations <n>          simulations per move (default 1000)
+  --threads <n>             games played at once (default: number of CPUs)
+  --plies <n>               start from all openings of n moves, up to symmetry (default 0)
+  --random-plies <n>        random moves played after the opening (default 0)
+  --temperature <t>         draw early moves in proportion to visits^(1/t), 0 to always play the best (default 1)
+  --temperature-plies <n>   moves drawn that way (default 10)
+  --seed <n>                seed of the random choices (default 0)";
+
+fn parse_args(args: &[String]) -> Result<(SelfPlay, Option<String>, Option<String>), String> {
+    let mut self_play = SelfPlay {
+        threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        ..Default::default()
+    };
+    let (mut out, mut read) = (None, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        let number = |value: String| {
+            value
+                .parse::<u64>()
+                .map_err(|_| format!("bad value for {}: {}", arg, value))
+        };
+        match arg.as_str() {
+            "--games" => self_play.games = number(value()?)? as u32,
+            "--time" => self_play.limits = Limits::time(number(value()?)? as u128),
+            "--iterations" => self_play.limits = Limits::iterations(number(value()?)? as i32),
+            "--threads" => self_play.threads = number(value()?)? as usize,
+            "--plies" => self_play.openings = tournament::openings(number(value()?)? as usize),
+            "--random-plies" => self_play.random_plies = number(value()?)? as usize,
+            "--temperature" => {
+                let value = value()?;
+                self_play.temperature = value
+                    .parse()
+                    .map_err(|_| format!("bad value for {}: {}", arg, value))?;
+            }
+            "--temperature-plies" => self_play.temperature_plies = number(value()?)? as usize,
+            "--seed" => self_play.seed = number(value()?)?,
+            "--out" => out = Some(value()?),
+            "--read" => read = Some(value()?),
+            spec if !spec.starts_with("--") => self_play.engine = EngineKind::parse(spec)?,
+            _ => return Err(format!("unknown option: {}", arg)),
+        }
+    }
+    if out.is_none() && read.is_none() {
+        return Err("--out or --read is needed".to_owned());
+    }
+
+    Ok((self_play, out, read))
+}
+
+/// Prints the number of samples and the results in a file.
+fn summarise(path: &str) -> io::Result<()> {
+    let reader = SampleReader::new(BufReader::new(File::open(path)?))?;
+    let (mut samples, mut games, mut value_error) = (0u64, 0u64, 0.0);
+    let mut wins = [0u64; 3];
+    for sample in reader {
+        let sample = sample?;
+        samples += 1;
+        let result = (sample.result as f32).signum();
+        value_error += ((sample.value - result) as f64).powi(2);
+        // Each game is counted at its last position, whose move ends it.
+        if sample.played != 0 && sample.board.play(sample.played).is_game_ended() {
+            games += 1;
+            let black_result = if sample.board.player() == 0 {
+                sample.result
+            } else {
+                -sample.result
+            };
+            wins[match black_result.signum() {
+                1 => 0,
+                -1 => 1,
+                _ => 2,
+            }] += 1;
+        }
+    }
+    println!("{} samples, {} games", samples, games);
+    println!(
+        "black wins {}, white wins {}, draws {}",
+        wins[0], wins[1], wins[2]
+    );
+    if samples > 0 {
+        println!(
+            "mean squared error of the search value against the result: {:.3}",
+            value_error / samples as f64
+        );
+    }
+
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (self_play, out, read) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let Some(path) = read {
+        return summarise(&path);
+    }
+
+    let path = out.unwrap();
+    let mut writer = SampleWriter::new(BufWriter::new(File::create(&path)?))?;
+    let mut error = None;
+    let (mut done, mut samples) = (0, 0);
+    self_play.run(|game, result| match result {
+        Ok(game_samples) => {
+            for sample in game_samples.iter() {
+                if let Err(err) = writer.write(sample) {
+                    error.get_or_insert(err);
+                }
+            }
+            done += 1;
+            samples += game_samples.len();
+            eprint!("\r{}/{} games, {} samples", done, self_play.games, samples);
+        }
+        Err(err) => eprintln!("\ngame {}: {}", game, err),
+    });
+    eprintln!();
+    if let Some(err) = error {
+        return Err(err);
+    }
+    writer.into_inner().flush()?;
+    println!("wrote {} samples to {}", samples, path);
+
+    Ok(())
+}
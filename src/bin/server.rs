@@ -0,0 +1,263 @@
+#![warn(clippy::all, rust_2018_idioms)]
+
+//! A game server for online play from the app or its web build:
+//!
+//! `cargo run --release --bin server -- [--port <port>] [--time <minutes>]`
+//!
+//! Clients connect over WebSocket, e.g. to `ws://<host>:9000`, and speak the
+//! JSON messages of `othello::othello::online`.
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use othello::othello::online::{
+    ClientMessage, Room, ServerMessage, DEFAULT_TIME, PROTOCOL_VERSION,
+};
+use tungstenite::error::ProtocolError;
+use tungstenite::{Message, WebSocket};
+use wasm_timer::Instant;
+
+const USAGE: &str = "\
+usage: server [options]
+  --port <port>         port to listen on (default 9000)
+  --time <minutes>      time per side (default 5)";
+
+/// Interval at which clocks are checked and sockets polled.
+const TICK: Duration = Duration::from_millis(100);
+
+struct RoomEntry {
+    room: Room,
+    /// The clients in the room and where to send their messages.
+    clients: Vec<(u64, Sender<ServerMessage>)>,
+}
+
+impl RoomEntry {
+    fn broadcast(&self) {
+        let state = ServerMessage::State(self.room.state(Instant::now()));
+        for (_, client) in self.clients.iter() {
+            let _ = client.send(state.clone());
+        }
+    }
+}
+
+struct Server {
+    rooms: HashMap<String, RoomEntry>,
+    time: Duration,
+}
+
+type Shared = Arc<Mutex<Server>>;
+
+impl Server {
+    /// Handles a message from client `id`, which is in `room` if it joined
+    /// one. Errors are sent back to the client.
+    fn handle(
+        &mut self,
+        id: u64,
+        room: &mut Option<String>,
+        message: ClientMessage,
+        client: &Sender<ServerMessage>,
+    ) -> Result<(), String> {
+        let now = Instant::now();
+        if let ClientMessage::Join {
+            version,
+            room: name,
+            name: player,
+            seat,
+        } = message
+        {
+            if version != PROTOCOL_VERSION {
+                return Err(format!(
+                    "protocol version {} is not supported (expected {})",
+                    version, PROTOCOL_VERSION
+                ));
+            }
+            if room.is_some() {
+                return Err("already in a room".to_owned());
+            }
+            let time = self.time;
+            let entry = self.rooms.entry(name.clone()).or_insert_with(|| RoomEntry {
+                room: Room::new(time),
+                clients: Vec::new(),
+            });
+            let seat = entry.room.join(id, &player, seat, now)?;
+            entry.clients.push((id, client.clone()));
+            let _ = client.send(ServerMessage::Joined {
+                room: name.clone(),
+                seat,
+            });
+            entry.broadcast();
+            *room = Some(name);
+            return Ok(());
+        }
+
+        let entry = room
+            .as_ref()
+            .and_then(|name| self.rooms.get_mut(name))
+            .ok_or("join a room first")?;
+        match message {
+            ClientMessage::Move { square } => entry.room.play(id, &square, now)?,
+            ClientMessage::Resign => entry.room.resign(id, now)?,
+            ClientMessage::Rematch => {
+                entry.room.rematch(id, now)?;
+                // The players swapped colours; tell them before the new state.
+                let name = room.as_ref().unwrap();
+                for (client_id, client) in entry.clients.iter() {
+                    let _ = client.send(ServerMessage::Joined {
+                        room: name.clone(),
+                        seat: entry.room.seat_of(*client_id),
+                    });
+                }
+            }
+            ClientMessage::Join { .. } => unreachable!(),
+        }
+        entry.broadcast();
+
+        Ok(())
+    }
+
+    fn leave(&mut self, id: u64, room: &str) {
+        if let Some(entry) = self.rooms.get_mut(room) {
+            entry.room.leave(id, Instant::now());
+            entry.clients.retain(|(client, _)| *client != id);
+            if entry.room.is_empty() {
+                self.rooms.remove(room);
+            } else {
+                entry.broadcast();
+            }
+        }
+    }
+}
+
+/// Serves one client until it disconnects.
+fn serve(stream: TcpStream, id: u64, server: Shared) -> Result<(), String> {
+    let mut socket = tungstenite::accept(stream).map_err(|err| err.to_string())?;
+    socket
+        .get_ref()
+        .set_read_timeout(Some(TICK))
+        .map_err(|err| err.to_string())?;
+    let (sender, receiver) = mpsc::channel();
+    let mut room = None;
+    let result = relay(&mut socket, id, &server, &mut room, &sender, &receiver);
+    if let Some(room) = room {
+        server.lock().unwrap().leave(id, &room);
+    }
+
+    result
+}
+
+fn relay(
+    socket: &mut WebSocket<TcpStream>,
+    id: u64,
+    server: &Shared,
+    room: &mut Option<String>,
+    sender: &Sender<ServerMessage>,
+    receiver: &Receiver<ServerMessage>,
+) -> Result<(), String> {
+    loop {
+        while let Ok(message) = receiver.try_recv() {
+            let text = serde_json::to_string(&message).unwrap();
+            socket
+                .send(Message::Text(text))
+                .map_err(|err| err.to_string())?;
+        }
+        let text = match socket.read() {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => continue,
+            Err(tungstenite::Error::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                continue
+            }
+            Err(tungstenite::Error::ConnectionClosed)
+            | Err(tungstenite::Error::Protocol(ProtocolError::ResetWithoutClosingHandshake)) => {
+                return Ok(())
+            }
+            Err(err) => return Err(err.to_string()),
+        };
+        let result = serde_json::from_str(&text)
+            .map_err(|err| format!("bad message: {}", err))
+            .and_then(|message| server.lock().unwrap().handle(id, room, message, sender));
+        if let Err(message) = result {
+            let _ = sender.send(ServerMessage::Error { message });
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<(u16, Duration), String> {
+    let mut port = 9000;
+    let mut time = DEFAULT_TIME;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {}", arg))?;
+        let bad = || format!("bad value for {}: {}", arg, value);
+        match arg.as_str() {
+            "--port" => port = value.parse().map_err(|_| bad())?,
+            "--time" => {
+                let minutes: f64 = value.parse().map_err(|_| bad())?;
+                if minutes <= 0.0 {
+                    return Err(bad());
+                }
+                time = Duration::from_secs_f64(minutes * 60.0);
+            }
+            _ => return Err(format!("unknown option: {}", arg)),
+        }
+    }
+
+    Ok((port, time))
+}
+
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (port, time) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let server: Shared = Arc::new(Mutex::new(Server {
+        rooms: HashMap::new(),
+        time,
+    }));
+
+    // Flags fall even when nobody moves.
+    let ticker = server.clone();
+    thread::spawn(move || loop {
+        thread::sleep(TICK);
+        let mut server = ticker.lock().unwrap();
+        for entry in server.rooms.values_mut() {
+            if entry.room.tick(Instant::now()) {
+                entry.broadcast();
+            }
+        }
+    });
+
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    println!("listening on port {}", port);
+    for (id, stream) in listener.incoming().enumerate() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
+        let server = server.clone();
+        thread::spawn(move || {
+            let peer = stream.peer_addr().map_or(String::new(), |a| a.to_string());
+            if let Err(err) = serve(stream, id as u64, server) {
+                eprintln!("{}: {}", peer, err);
+            }
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,211 @@
+#![warn(clippy::all, rust_2018_idioms)]
+
+//! Plays engines against each other and reports their scores, Elo
+//! differences and SPRT results:
+//!
+//! `cargo run --release --bin tournament -- [options] <engine spec>...`
+//!
+//! See `USAGE` for the options. Specs are as for `EngineKind::parse`, e.g.
+//! `mcts:cp=1.5 mcts:cp=1.0 greedy`.
+
+use std::fs::File;
+use std::io::{self, Write};
+
+use othello::othello::engine::{EngineKind, Limits};
+use othello::othello::ggf::GgfGame;
+use othello::othello::tournament::{self, Sprt, SprtStatus, Tournament};
+
+const USAGE: &str = "\
+usage: tournament [options] <engine spec>...
+  --gauntlet            the first engine plays each of the others (default: round robin)
+  --games <n>           games per pair, each opening played with both colours (default 20)
+  --time <ms>           time per move (default 100)
+  --iterations <n>      simulations per move instead of a time limit
+  --plies <n>           use all openings of n moves, up to symmetry (default 4)
+  --openings <file>     read openings from a file, one line of moves such as f5d6c3 each
+  --threads <n>         games played at once (default: number of CPUs)
+  --out <file>          write every game to a GGF file
+  --sprt <elo0,elo1>    stop a pair once the test accepts one of the hypotheses";
+
+fn parse_args(args: &[String]) -> Result<(Tournament, Option<String>), String> {
+    let mut tournament = Tournament {
+        engines: Vec::new(),
+        gauntlet: false,
+        games: 20,
+        limits: Limits::time(100),
+        openings: Vec::new(),
+        threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        sprt: None,
+    };
+    let mut plies = 4;
+    let mut out = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        let number = |value: String| {
+            value
+                .parse::<u32>()
+                .map_err(|_| format!("bad value for {}: {}", arg, value))
+        };
+        match arg.as_str() {
+            "--gauntlet" => tournament.gauntlet = true,
+            "--games" => tournament.games = number(value()?)?,
+            "--time" => tournament.limits = Limits::time(number(value()?)? as u128),
+            "--iterations" => tournament.limits = Limits::iterations(number(value()?)? as i32),
+            "--plies" => plies = number(value()?)? as usize,
+            "--openings" => {
+                let path = value()?;
+                let text =
+                    std::fs::read_to_string(&path).map_err(|err| format!("{}: {}", path, err))?;
+                tournament.openings = tournament::parse_openings(&text)?;
+            }
+            "--threads" => tournament.threads = number(value()?)? as usize,
+            "--out" => out = Some(value()?),
+            "--sprt" => {
+                let value = value()?;
+                let bounds: Vec<f64> = value.split(',').filter_map(|s| s.parse().ok()).collect();
+                match bounds[..] {
+                    [elo0, elo1] if elo0 < elo1 => tournament.sprt = Some(Sprt::new(elo0, elo1)),
+                    _ => return Err(format!("bad value for --sprt: {}", value)),
+                }
+            }
+            spec if !spec.starts_with("--") => tournament.engines.push(EngineKind::parse(spec)?),
+            _ => return Err(format!("unknown option: {}", arg)),
+        }
+    }
+    if tournament.engines.len() < 2 {
+        return Err("at least two engines are needed".to_owned());
+    }
+    if tournament.openings.is_empty() {
+        tournament.openings = tournament::openings(plies);
+    }
+
+    Ok((tournament, out))
+}
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (tournament, out) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let mut out = match out {
+        Some(path) => Some(File::create(path)?),
+        None => None,
+    };
+    let names: Vec<String> = tournament.engines.iter().map(|kind| kind.spec()).collect();
+    for (i, name) in names.iter().enumerate() {
+        println!("{}: {}", i + 1, name);
+    }
+    println!(
+        "{} pairs, {} games each, {} openings, {} threads",
+        tournament.pairs().len(),
+        tournament.games,
+        tournament.openings.len(),
+        tournament.threads
+    );
+
+    let mut error = None;
+    let results = tournament.run(|game, standing| {
+        let record = &game.record;
+        let forfeit = match &record.forfeit {
+            Some((player, reason)) => {
+                let engine = if *player == 0 { game.black } else { game.white };
+                format!(" ({} forfeits: {})", engine + 1, reason)
+            }
+            None => String::new(),
+        };
+        println!(
+            "{} vs {}: {}-{}{}  [{} vs {}: +{} ={} -{}]",
+            game.black + 1,
+            game.white + 1,
+            record.black,
+            record.white,
+            forfeit,
+            standing.a + 1,
+            standing.b + 1,
+            standing.score.wins,
+            standing.score.draws,
+            standing.score.losses
+        );
+        if let Some(file) = out.as_mut() {
+            let ggf = GgfGame::new(record.game(), &names[game.black], &names[game.white]);
+            if let Err(err) = writeln!(file, "{}", ggf) {
+                error.get_or_insert(err);
+            }
+        }
+    });
+    if let Some(err) = error {
+        return Err(err);
+    }
+
+    println!();
+    for result in results.iter() {
+        let (elo, margin) = tournament::elo_with_margin(&result.score);
+        let sprt = match (tournament.sprt, result.sprt) {
+            (Some(sprt), Some(status)) => {
+                let (lower, upper) = sprt.bounds();
+                let status = match status {
+                    SprtStatus::Continue => "undecided",
+                    SprtStatus::AcceptH0 => "H0 accepted",
+                    SprtStatus::AcceptH1 => "H1 accepted",
+                };
+                format!(
+                    ", SPRT [{}, {}] LLR {:.2} ({:.2}, {:.2}): {}",
+                    sprt.elo0,
+                    sprt.elo1,
+                    sprt.llr(&result.score),
+                    lower,
+                    upper,
+                    status
+                )
+            }
+            _ => String::new(),
+        };
+        println!(
+            "{} vs {}: +{} ={} -{} ({:.1}%), Elo {:+.0} +- {:.0}{}",
+            result.a + 1,
+            result.b + 1,
+            result.score.wins,
+            result.score.draws,
+            result.score.losses,
+            result.score.ratio() * 100.0,
+            elo,
+            margin,
+            sprt
+        );
+    }
+
+    if !tournament.gauntlet && tournament.engines.len() > 2 {
+        println!();
+        let mut points = vec![(0.0, 0); names.len()];
+        for result in results.iter() {
+            let score = result.score;
+            let a_points = score.wins as f64 + score.draws as f64 / 2.0;
+            points[result.a].0 += a_points;
+            points[result.a].1 += score.games();
+            points[result.b].0 += score.games() as f64 - a_points;
+            points[result.b].1 += score.games();
+        }
+        let mut order: Vec<usize> = (0..names.len()).collect();
+        order.sort_by(|&a, &b| points[b].0.total_cmp(&points[a].0));
+        for i in order {
+            println!(
+                "{:>6.1}/{:<4} {}: {}",
+                points[i].0,
+                points[i].1,
+                i + 1,
+                names[i]
+            );
+        }
+    }
+
+    Ok(())
+}
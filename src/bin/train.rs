@@ -0,0 +1,135 @@
+#![warn(clippy::all, rust_2018_idioms)]
+
+//! Fits pattern evaluation weights to recorded games:
+//!
+//! `cargo run --release --bin train -- [options] --out <weights> <file>...`
+//!
+//! Files are self-play samples (see `othello::othello::selfplay`) or GGF
+//! games. The weights are played with the `pattern:<weights>` engine.
+
+use std::fs;
+
+use othello::othello::pattern::{self, Evaluator, PHASES};
+use othello::othello::train::{self, Target, Trainer};
+
+const USAGE: &str = "\
+usage: train [options] --out <weights> <file>...
+  --target <target>     final, solve:<empties> or td:<lambda> (default final)
+  --epochs <n>          passes over the training games (default 10)
+  --rate <r>            share of the error corrected by each update (default 0.1)
+  --validation <share>  share of the games held out (default 0.1)
+  --init <weights>      start from these weights instead of zeros
+  --solve-nodes <n>     positions searched to solve each one (default 1000000)
+  --threads <n>         threads solving positions (default: number of CPUs)
+  --seed <n>            seed of the split and the order of updates (default 0)";
+
+struct Options {
+    trainer: Trainer,
+    out: String,
+    init: Option<String>,
+    files: Vec<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut trainer = Trainer {
+        threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        ..Default::default()
+    };
+    let (mut out, mut init, mut files) = (None, None, Vec::new());
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            files.push(arg.clone());
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {}", arg))?;
+        let bad = || format!("bad value for {}: {}", arg, value);
+        match arg.as_str() {
+            "--target" => trainer.target = Target::parse(value)?,
+            "--epochs" => trainer.epochs = value.parse().map_err(|_| bad())?,
+            "--rate" => trainer.rate = value.parse().map_err(|_| bad())?,
+            "--validation" => {
+                trainer.validation = value.parse().map_err(|_| bad())?;
+                if !(0.0..1.0).contains(&trainer.validation) {
+                    return Err(bad());
+                }
+            }
+            "--solve-nodes" => trainer.solve_nodes = value.parse().map_err(|_| bad())?,
+            "--threads" => trainer.threads = value.parse().map_err(|_| bad())?,
+            "--seed" => trainer.seed = value.parse().map_err(|_| bad())?,
+            "--init" => init = Some(value.clone()),
+            "--out" => out = Some(value.clone()),
+            _ => return Err(format!("unknown option: {}", arg)),
+        }
+    }
+    if files.is_empty() {
+        return Err("no games to train on".to_owned());
+    }
+
+    Ok(Options {
+        trainer,
+        out: out.ok_or("--out is needed")?,
+        init,
+        files,
+    })
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let fail = |err: String| -> ! {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    };
+
+    let mut games = Vec::new();
+    for path in options.files.iter() {
+        let data = fs::read(path).unwrap_or_else(|err| fail(format!("{}: {}", path, err)));
+        let read =
+            train::read_games(&data).unwrap_or_else(|err| fail(format!("{}: {}", path, err)));
+        games.extend(read);
+    }
+    let positions: usize = games.iter().map(|game| game.positions.len()).sum();
+    println!("{} games, {} positions", games.len(), positions);
+    let mut evaluator = match &options.init {
+        Some(path) => {
+            Evaluator::load(path).unwrap_or_else(|err| fail(format!("{}: {}", path, err)))
+        }
+        None => Evaluator::default(),
+    };
+
+    let mut last = None;
+    options.trainer.train(&mut evaluator, &games, |report| {
+        println!(
+            "epoch {}: training error {:.2}, validation error {:.2} disks",
+            report.epoch,
+            report.training.rmse(None),
+            report.validation.rmse(None)
+        );
+        last = Some(report.clone());
+    });
+    if let Some(report) = last {
+        println!("validation error by empty squares:");
+        for phase in (0..PHASES).rev() {
+            println!(
+                "  {:>5}: {:6.2} disks over {} positions",
+                pattern::phase_name(phase),
+                report.validation.rmse(Some(phase)),
+                report.validation.positions[phase]
+            );
+        }
+    }
+
+    if let Err(err) = evaluator.save(&options.out) {
+        fail(format!("{}: {}", options.out, err));
+    }
+    println!("wrote {}", options.out);
+}
@@ -0,0 +1,72 @@
+#![warn(clippy::all, rust_2018_idioms)]
+
+//! Tunes the `MCTS` search parameters by self-play.
+//!
+//! Every candidate plays a match against `SearchParams::default()`:
+//!
+//! `cargo run --release --bin tune -- [games] [ms per move]`
+
+use othello::othello::arena;
+use othello::othello::moai::{BitBoard, FinalMovePolicy, SearchParams, SelectionPolicy, MCTS};
+
+fn player(params: SearchParams, time: u128) -> impl FnMut(&BitBoard) -> u64 {
+    move |board: &BitBoard| MCTS::from_params(params).run(*board, time).0
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let games = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(20);
+    let time = args.get(2).and_then(|s| s.parse().ok()).unwrap_or(50);
+    let baseline = SearchParams::default();
+
+    println!("{} games per candidate, {} ms per move", games, time);
+    println!("baseline: {:?}", baseline);
+
+    let mut best = (baseline, 0.5);
+    for selection in SelectionPolicy::ALL.iter() {
+        for cp in [0.25, 0.5, 1.0, 1.5, 2.0].iter() {
+            let candidate = SearchParams {
+                cp: *cp,
+                selection: *selection,
+                ..baseline
+            };
+            if candidate == baseline {
+                continue;
+            }
+            let score = arena::play_match(games, player(candidate, time), player(baseline, time));
+            println!(
+                "{:<10} cp={:<4} +{} ={} -{} ({:.1}%)",
+                selection.name(),
+                cp,
+                score.wins,
+                score.draws,
+                score.losses,
+                score.ratio() * 100.0
+            );
+            if score.ratio() > best.1 {
+                best = (candidate, score.ratio());
+            }
+        }
+    }
+
+    for final_move in FinalMovePolicy::ALL.iter() {
+        let candidate = SearchParams {
+            final_move: *final_move,
+            ..best.0
+        };
+        if candidate == best.0 {
+            continue;
+        }
+        let score = arena::play_match(games, player(candidate, time), player(best.0, time));
+        println!(
+            "{:<10} +{} ={} -{} ({:.1}% vs best)",
+            final_move.name(),
+            score.wins,
+            score.draws,
+            score.losses,
+            score.ratio() * 100.0
+        );
+    }
+
+    println!("best: {:?} ({:.1}%)", best.0, best.1 * 100.0);
+}
@@ -4,7 +4,6 @@ mod app;
 pub use app::TemplateApp;
 pub mod othello;
 
-extern crate wasm_timer;
 #[cfg(target_arch = "wasm32")]
 extern crate web_sys;
 
@@ -1,23 +1,209 @@
+pub mod analysis;
+pub mod animation;
+pub mod arena;
 pub mod board;
+pub mod difficulty;
+pub mod editor;
+pub mod engine;
+pub mod game;
+pub mod ggf;
 pub mod moai;
+pub mod mode;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod net;
+pub mod network;
+pub mod online;
+pub mod overlay;
+pub mod pattern;
+pub mod ponder;
+pub mod selfplay;
+pub mod thinker;
+pub mod tournament;
+pub mod train;
 
-use board::*;
 #[cfg(target_arch = "wasm32")]
 use crate::log;
+use board::*;
+use engine::{Engine, EngineKind, Limits, SearchInfo};
 
 use eframe::{egui, epi};
 
+/// Simulations of an analysis, which keep its search tree to about a hundred
+/// megabytes. The tree is then dropped and the search starts over.
+const ANALYSIS_ITERATIONS: i32 = 500_000;
+
+/// Bounds of each search of an analysis.
+const ANALYSIS_SLICE: Limits = Limits {
+    time: Some(1000),
+    iterations: Some(50_000),
+};
+
 #[cfg_attr(feature = "persistence", derive(serde::Deserialize, serde::Serialize))]
 #[cfg_attr(feature = "persistence", serde(default))] // if we add new fields, give them default values when deserializing old state
 pub struct OthelloApp {
     // #[cfg_attr(feature = "persistence", serde(skip))]
     board: board::Board,
+    /// The moves of the local game.
+    game: game::Game,
+    /// Moves of `game` shown on the board. The AI only moves at its end.
+    ply: usize,
+    /// Moves taken back, the next one last.
+    redo: Vec<u64>,
+    /// Lines left behind by playing at an earlier ply, see `play_from_here`.
+    variations: Vec<game::Game>,
+    /// A move made at an earlier ply, until playing it branches or truncates
+    /// the game.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    pending_move: Option<u64>,
+    /// The position being set up, while the editor is open.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    editor: Option<editor::Editor>,
+    mode: mode::GameMode,
+    /// The mode chosen before analysing a position from the editor, which
+    /// takes over again from the next new game.
+    played_mode: Option<mode::GameMode>,
+    /// The mode being edited in the new game window, while it is open.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    new_mode: Option<mode::GameMode>,
+    /// Keep searching while the human is thinking.
+    ponder: bool,
+    /// Where the game over window saves games.
+    #[cfg(not(target_arch = "wasm32"))]
+    save_path: String,
+    /// The outcome of the last save.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    save_status: Option<String>,
+    overlays: overlay::Overlays,
+    animation_speed: animation::Speed,
+    /// The last move being animated.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    animation: Option<animation::Animation>,
+    /// The position drawn last, to notice moves in network games.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    shown: moai::BitBoard,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    last_move: Option<overlay::LastMove>,
+    /// The engine of each side, while it is not searching.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    engines: [Option<Box<dyn Engine>>; 2],
+    /// What `engines` were created from, see `AiSettings::engine_spec`.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    engine_specs: [String; 2],
+    /// The side whose engine is pondering, and its search.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    ponderer: Option<(usize, ponder::Ponderer)>,
+    /// The AI's search for its move, while it runs.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    thinker: Option<thinker::Thinker>,
+    /// Search the position shown, and draw what the search thinks of each
+    /// legal move.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    analyse: bool,
+    /// The engine of the analysis, whose time limits are ignored.
+    analysis_ai: mode::AiSettings,
+    /// What `analysis_engine` was created from.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    analysis_spec: String,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    analyser: Option<thinker::Thinker>,
+    /// The engine of `analyser`, while it is not searching.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    analysis_engine: Option<Box<dyn Engine>>,
+    /// The position analysed and the latest progress of its search.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    analysis: Option<(moai::BitBoard, SearchInfo)>,
+    /// Simulations of the analysis' finished slices since its tree was last
+    /// dropped.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    analysis_iterations: i32,
+    /// Why the analysis stopped, if its engine failed.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    analysis_error: Option<String>,
+    /// The AI does not move until resumed, after its search was cancelled.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    ai_paused: bool,
+    /// Why the AI's last search failed, which also pauses it.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    ai_error: Option<String>,
+    /// Port to host network games on.
+    #[cfg(not(target_arch = "wasm32"))]
+    net_port: u16,
+    /// Host to join, as `address:port`.
+    #[cfg(not(target_arch = "wasm32"))]
+    net_address: String,
+    /// The network game being played, if any.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    net: Option<net::NetSession>,
+    /// Why hosting failed or a move was refused, shown until the next try.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    net_error: Option<String>,
+    /// WebSocket address of a game server.
+    online_url: String,
+    online_room: String,
+    online_name: String,
+    /// `None` takes whichever colour is free.
+    online_seat: Option<online::Seat>,
+    /// The room joined on a server, if any.
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    online: Option<online::client::OnlineClient>,
 }
 
 impl Default for OthelloApp {
     fn default() -> Self {
         Self {
             board: Default::default(),
+            game: Default::default(),
+            ply: 0,
+            redo: Vec::new(),
+            variations: Vec::new(),
+            pending_move: None,
+            editor: None,
+            mode: Default::default(),
+            played_mode: None,
+            new_mode: None,
+            ponder: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            save_path: "game.ggf".to_owned(),
+            #[cfg(not(target_arch = "wasm32"))]
+            save_status: None,
+            overlays: Default::default(),
+            animation_speed: Default::default(),
+            animation: None,
+            shown: moai::BitBoard::initial(),
+            last_move: None,
+            engines: [None, None],
+            engine_specs: Default::default(),
+            ponderer: None,
+            thinker: None,
+            analyse: false,
+            analysis_ai: mode::AiSettings {
+                difficulty: None,
+                ..Default::default()
+            },
+            analysis_spec: String::new(),
+            analyser: None,
+            analysis_engine: None,
+            analysis: None,
+            analysis_iterations: 0,
+            analysis_error: None,
+            ai_paused: false,
+            ai_error: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            net_port: net::DEFAULT_PORT,
+            #[cfg(not(target_arch = "wasm32"))]
+            net_address: format!("127.0.0.1:{}", net::DEFAULT_PORT),
+            #[cfg(not(target_arch = "wasm32"))]
+            net: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            net_error: None,
+            online_url: "ws://localhost:9000".to_owned(),
+            online_room: "lobby".to_owned(),
+            online_name: "guest".to_owned(),
+            online_seat: None,
+            online: None,
         }
     }
 }
@@ -27,7 +213,12 @@ impl epi::App for OthelloApp {
         "othello"
     }
 
-    fn setup(&mut self, _ctx: &egui::CtxRef, _frame: &mut epi::Frame<'_>, _storage: Option<&dyn epi::Storage>) {
+    fn setup(
+        &mut self,
+        _ctx: &egui::CtxRef,
+        _frame: &mut epi::Frame<'_>,
+        _storage: Option<&dyn epi::Storage>,
+    ) {
         #[cfg(feature = "persistence")]
         if let Some(storage) = _storage {
             *self = epi::get_value(storage, epi::APP_KEY).unwrap_or_default()
@@ -41,102 +232,1361 @@ impl epi::App for OthelloApp {
 
     fn update(&mut self, ctx: &egui::CtxRef, _frame: &mut epi::Frame<'_>) {
         ctx.set_pixels_per_point(3.0);
-        egui::CentralPanel::default().show(&ctx, |ui| {
-            egui::Grid::new("Board").spacing(egui::vec2(0.0, 0.0)).show(ui, |ui| {
-                let white_color = egui::Color32::from_rgb(255, 255, 255);
-                let black_color = egui::Color32::from_rgb(0, 0, 0);
-
-                while self.board.player == 1 && !self.board.is_game_ended() {
-                    if self.board.legal_moves().len() == 0 {
-                        self.board.player = self.board.next_player();
-                        break;
-                    }
-                    self.play_ai();
-                    if self.board.legal_moves().len() == 0 {
-                        self.board.player = self.board.next_player();
-                    }
-                }
-
-                let mut painters = Vec::new();
-                let mut responses = Vec::new();
-                let mut position = None;
-
-                for y in 0..8 {
-                    let mut column_response = Vec::new();
-                    let mut column_painter = Vec::new();
-                    for x in 0..8 {
-                        let (response, painter) = ui.allocate_painter(egui::vec2(40.0, 40.0), egui::Sense::click_and_drag());
-                        if response.clicked() {
-                            position = Some((x, y));
-                            // if self.board.is_legal_move(self.board.player_disk(), x, y) {
-                            //     self.board = self.board.play(x, y);
-                            // }
-                        }
-                        let rect = response.rect;
-                        painter.rect(rect, 0.0, egui::Color32::from_rgb(0, 200, 0), egui::Stroke::new(1.0, egui::Color32::from_rgb(0, 0, 0)));
-                        column_response.push(response);
-                        column_painter.push(painter);
+        if let Some((_, ponderer)) = self.ponderer.as_mut() {
+            ponderer.step(20);
+            #[cfg(target_arch = "wasm32")]
+            ctx.request_repaint();
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(net) = self.net.as_mut() {
+            if net.poll() {
+                self.board = net.board().clone();
+            }
+            // Keep polling for the peer's messages.
+            ctx.request_repaint();
+        }
+        if let Some(client) = self.online.as_mut() {
+            if client.poll() {
+                let state = client.state.as_ref().and_then(online::RoomState::game);
+                if let Some(game) = state {
+                    self.board = to_board(&game.position());
+                }
+            }
+            // Keep polling, and run the clocks.
+            ctx.request_repaint();
+        }
+        if self.animation.as_ref().is_some_and(|a| a.is_finished()) {
+            self.animation = None;
+        }
+        let net_game = self.is_net_game();
+        if net_game {
+            self.cancel_ai();
+        } else if let Some(editor) = &self.editor {
+            self.board = to_board(&editor.board());
+        } else {
+            self.sync_board();
+            self.keyboard_shortcuts(ctx);
+            self.drive_ai();
+        }
+        self.drive_analysis(net_game);
+        if self.thinker.is_some()
+            || self.analyser.is_some()
+            || self.animation.is_some()
+            || (!net_game && self.is_ai_to_move())
+        {
+            ctx.request_repaint();
+        }
+        self.new_game_window(ctx);
+        if self.editor.is_some() && !net_game {
+            self.editor_window(ctx);
+        } else if !net_game {
+            self.branch_window(ctx);
+            self.game_over_window(ctx);
+            egui::SidePanel::right("Moves").show(ctx, |ui| self.moves_ui(ui));
+        }
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::Grid::new("Board")
+                .spacing(egui::vec2(0.0, 0.0))
+                .show(ui, |ui| {
+                    let white_color = egui::Color32::from_rgb(255, 255, 255);
+                    let black_color = egui::Color32::from_rgb(0, 0, 0);
+
+                    let mut painters = Vec::new();
+                    let mut responses = Vec::new();
+                    let mut position = None;
 
+                    for y in 0..8 {
+                        let mut column_response = Vec::new();
+                        let mut column_painter = Vec::new();
+                        for x in 0..8 {
+                            let (response, painter) = ui.allocate_painter(
+                                egui::vec2(40.0, 40.0),
+                                egui::Sense::click_and_drag(),
+                            );
+                            if response.clicked() {
+                                position = Some((x, y));
+                                // if self.board.is_legal_move(self.board.player_disk(), x, y) {
+                                //     self.board = self.board.play(x, y);
+                                // }
+                            }
+                            let rect = response.rect;
+                            painter.rect(
+                                rect,
+                                0.0,
+                                egui::Color32::from_rgb(0, 200, 0),
+                                egui::Stroke::new(1.0, egui::Color32::from_rgb(0, 0, 0)),
+                            );
+                            column_response.push(response);
+                            column_painter.push(painter);
+                        }
+                        responses.push(column_response);
+                        painters.push(column_painter);
+                        ui.end_row();
                     }
-                    responses.push(column_response);
-                    painters.push(column_painter);
-                    ui.end_row();
-                }
 
-                if let Some((x, y)) = position {
-                    if self.board.is_legal_move(self.board.player_disk(), x, y) {
-                        self.board = self.board.play(x, y);
+                    if let Some((x, y)) = position.filter(|_| net_game) {
+                        self.play_net(x, y);
+                    } else if let Some(editor) = self.editor.as_mut() {
+                        if let Some((x, y)) = position {
+                            editor.apply(1 << (63 - (y * 8 + x)));
+                            self.board = to_board(&editor.board());
+                        }
+                    } else if let Some((x, y)) = position {
+                        if self.mode.is_human(self.board.player)
+                            && self.thinker.is_none()
+                            && self.board.is_legal_move(self.board.player_disk(), x, y)
+                        {
+                            let position = 1 << (63 - (y * 8 + x));
+                            if self.ply < self.game.moves.len() {
+                                if self.mode.allow_undo {
+                                    self.pending_move = Some(position);
+                                }
+                            } else {
+                                self.play_move(position);
+                            }
+                        }
                     }
-                }
+                    self.track_last_move(net_game);
 
-                for y in 0..8 {
-                    for x in 0..8 {
-                        let rect = responses[y][x].rect;
-                        match self.board.disks[y][x] {
-                            Disk::White => {
-                                painters[y][x].circle_filled(rect.center(), 18.0, white_color);
+                    let legal_bits = if self.overlays.legal_moves {
+                        self.position().legal_bits()
+                    } else {
+                        0
+                    };
+                    let (last_move, flipped) = match self.last_move {
+                        Some(last) => (
+                            last.position * self.overlays.last_move as u64,
+                            last.flipped * self.overlays.flips as u64,
+                        ),
+                        None => (0, 0),
+                    };
+                    let analysis = self.current_analysis();
+                    let elapsed = self.animation.as_ref().map(|a| a.elapsed());
+                    for y in 0..8 {
+                        for x in 0..8 {
+                            let rect = responses[y][x].rect;
+                            let painter = &painters[y][x];
+                            let bit = 1u64 << (63 - (y * 8 + x));
+                            let color = match self.board.disks[y][x] {
+                                Disk::White => white_color,
+                                Disk::Black => black_color,
+                                Disk::Empty => {
+                                    let stat = analysis.as_ref().and_then(|info| {
+                                        info.moves
+                                            .iter()
+                                            .find(|stat| stat.position == bit && stat.visits > 0)
+                                    });
+                                    if let Some(stat) = stat {
+                                        let best = analysis.as_ref().unwrap().best_move == bit;
+                                        heat_square(painter, rect, stat.score, best);
+                                    } else if legal_bits & bit != 0 {
+                                        let hint = egui::Color32::from_black_alpha(60);
+                                        painter.circle_filled(rect.center(), 5.0, hint);
+                                    }
+                                    continue;
+                                }
+                            };
+                            let frame = self
+                                .animation
+                                .as_ref()
+                                .zip(elapsed)
+                                .and_then(|(animation, elapsed)| animation.frame(bit, elapsed));
+                            let (color, radius) = match frame {
+                                Some(frame) if frame.before => {
+                                    let before = if color == white_color {
+                                        black_color
+                                    } else {
+                                        white_color
+                                    };
+                                    (before, 18.0 * frame.scale)
+                                }
+                                Some(frame) => (color, 18.0 * frame.scale),
+                                None => (color, 18.0),
+                            };
+                            painter.circle_filled(rect.center(), radius, color);
+                            if flipped & bit != 0 {
+                                let stroke = egui::Stroke::new(2.0, egui::Color32::GOLD);
+                                painter.circle_stroke(rect.center(), 18.0, stroke);
                             }
-                            Disk::Black => {
-                                painters[y][x].circle_filled(rect.center(), 18.0, black_color);
+                            if last_move & bit != 0 {
+                                painter.circle_filled(rect.center(), 4.0, egui::Color32::RED);
                             }
-                            _ => {}
                         }
                     }
-                }
+                });
 
-                if self.board.is_game_ended() {
+            let (black, white) = self.board.num_disk();
+            ui.add(
+                egui::Label::new(format!("Black: {}", black))
+                    .heading()
+                    .monospace(),
+            );
+            ui.add(
+                egui::Label::new(format!("White: {}", white))
+                    .heading()
+                    .monospace(),
+            );
+            if !net_game && self.editor.is_none() {
+                self.pass_ui(ui);
+            }
+            if let Some(thinker) = self.thinker.as_mut() {
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "Thinking… {:.1} s, {} simulations",
+                        thinker.elapsed() as f64 / 1000.0,
+                        thinker.info().iterations
+                    ));
+                    if ui.button("Move now").clicked() {
+                        thinker.move_now();
+                    }
+                });
+                if ui.button("Cancel").clicked() {
+                    self.cancel_ai();
+                    self.ai_paused = true;
+                }
+            } else if self.ai_paused && !net_game {
+                if let Some(err) = &self.ai_error {
+                    ui.colored_label(egui::Color32::RED, format!("The AI failed: {}", err));
+                }
+                ui.horizontal(|ui| {
+                    ui.label("The AI is paused.");
+                    if ui.button("Resume").clicked() {
+                        self.ai_paused = false;
+                        self.ai_error = None;
+                    }
+                });
+            }
+            let mut reset = false;
+            ui.horizontal(|ui| {
+                reset = ui.button("Reset").clicked();
+                if net_game {
                     return;
                 }
+                if ui.button("New game…").clicked() {
+                    let mode = self.played_mode.as_ref().unwrap_or(&self.mode);
+                    self.new_mode = Some(mode.clone());
+                }
+                if ui.button("Edit position").clicked() {
+                    self.open_editor();
+                }
+                if self.mode.allow_undo {
+                    let undo = egui::Button::new("Undo");
+                    if ui.add_enabled(self.can_undo(), undo).clicked() {
+                        self.undo();
+                    }
+                    let redo = egui::Button::new("Redo");
+                    if ui.add_enabled(!self.redo.is_empty(), redo).clicked() {
+                        self.redo();
+                    }
+                }
             });
-
-            let (black, white) = self.board.num_disk();
-            ui.add(egui::Label::new(format!("Black: {}", black)).heading().monospace());
-            ui.add(egui::Label::new(format!("White: {}", white)).heading().monospace());
-            let resp = ui.add(egui::Button::new("Reset"));
-            if resp.clicked() {
-                self.board = Board::default();
+            if reset && net_game {
+                self.new_net_game();
+            } else if reset {
+                self.new_game(moai::BitBoard::initial());
+            }
+            if !net_game {
+                ui.label(self.mode.name());
+            }
+            if ui.checkbox(&mut self.ponder, "Ponder").changed() && !self.ponder {
+                self.stop_pondering();
+            }
+            if !net_game {
+                if ui.checkbox(&mut self.analyse, "Analysis").changed() {
+                    self.analysis_error = None;
+                }
+                if let Some(err) = &self.analysis_error {
+                    ui.colored_label(egui::Color32::RED, format!("The analysis failed: {}", err));
+                }
+                if self.analyse {
+                    ui.indent("Analysis", |ui| {
+                        ai_settings_ui(&mut self.analysis_ai, true, ui)
+                    });
+                }
             }
+            if let Some(info) = self.current_analysis().filter(|info| info.iterations > 0) {
+                ui.label(format!(
+                    "Best {} ({:.0}%), {} simulations",
+                    game::square_name(info.best_move),
+                    win_rate(info.score) * 100.0,
+                    info.iterations
+                ));
+            }
+
+            egui::CollapsingHeader::new("Display").show(ui, |ui| {
+                ui.checkbox(&mut self.overlays.legal_moves, "Legal moves");
+                ui.checkbox(&mut self.overlays.last_move, "Last move");
+                ui.checkbox(&mut self.overlays.flips, "Flipped disks");
+                egui::ComboBox::from_label("Animation")
+                    .selected_text(self.animation_speed.name())
+                    .show_ui(ui, |ui| {
+                        for speed in animation::Speed::ALL.iter() {
+                            ui.selectable_value(&mut self.animation_speed, *speed, speed.name());
+                        }
+                    });
+            });
+            #[cfg(not(target_arch = "wasm32"))]
+            egui::CollapsingHeader::new("Network game").show(ui, |ui| {
+                self.net_ui(ui);
+            });
+            egui::CollapsingHeader::new("Online game").show(ui, |ui| {
+                self.online_ui(ui);
+            });
         });
     }
 }
 
 impl OthelloApp {
-    pub fn play_ai(&mut self) {
-        let board = moai::BitBoard::from_strings(self.board.to_strings(), self.board.player);
-        let mut mcts = moai::MCTS::new(1.0, 1);
-        let (position, count) = mcts.run(board, 1000);
-        #[cfg(target_arch = "wasm32")]
-        log!("{}", count);
+    /// Starts the AI's search when it is to move, and plays its move once
+    /// found. Called every frame.
+    fn drive_ai(&mut self) {
+        if let Some(thinker) = self.thinker.as_mut() {
+            thinker.step(20);
+            // The previous move is shown in full before this one.
+            if self.animation.is_some() {
+                return;
+            }
+            if let Some((engine, result)) = thinker.poll() {
+                self.thinker = None;
+                self.finish_ai(engine, result);
+            }
+            return;
+        }
+        if self.board.is_game_ended() || self.ply < self.game.moves.len() {
+            return;
+        }
+        // Humans confirm their passes, see `pass_ui`.
+        if game::is_legal(&self.game.position(), 0) {
+            if self.mode.is_ai(self.board.player) && !self.ai_paused {
+                self.play_move(0);
+            }
+            return;
+        }
+        if !self.is_ai_to_move() {
+            return;
+        }
+
+        let side = self.board.player as usize;
+        let board = self.position();
+        self.stop_pondering();
+        let settings = &self.mode.ai[side];
+        let spec = settings.engine_spec();
+        let engine = match self.engines[side].take() {
+            Some(engine) if self.engine_specs[side] == spec => engine,
+            _ => settings.create_engine(),
+        };
+        let limits = settings.limits();
+        self.engine_specs[side] = spec;
+        self.thinker = Some(thinker::Thinker::start(engine, board, limits));
+    }
+
+    /// The position on the board, for the rules engine.
+    fn position(&self) -> moai::BitBoard {
+        moai::BitBoard::from_strings(self.board.to_strings(), self.board.player)
+    }
+
+    /// Updates `last_move`: the last disk placed up to the ply shown of a
+    /// local game, or in a network game, whatever move changed the disks on
+    /// the board. Moves of local games are animated by `play_move`.
+    fn track_last_move(&mut self, net_game: bool) {
+        let position = self.position();
+        if self.editor.is_some() {
+            self.last_move = None;
+            self.animation = None;
+        } else if net_game {
+            if position.bits() != self.shown.bits() {
+                self.last_move = overlay::LastMove::between(&self.shown, &position);
+                self.animation = self
+                    .last_move
+                    .and_then(|last| animation::Animation::new(last, self.animation_speed));
+            }
+        } else {
+            let moves = &self.game.moves[..self.ply];
+            self.last_move = moves.iter().rposition(|&m| m != 0).map(|ply| {
+                let position = moves[ply];
+                overlay::LastMove {
+                    position,
+                    flipped: self.game.position_at(ply).flips(position),
+                }
+            });
+        }
+        self.shown = position;
+    }
+
+    /// Keeps the analysis on the position shown while `analyse` is set. It
+    /// gives way to the AI's own search, and otherwise goes on in slices
+    /// that each pick up where the last one left off.
+    fn drive_analysis(&mut self, net_game: bool) {
+        let position = self.game.position_at(self.ply);
+        let wanted = self.analyse
+            && !net_game
+            && self.editor.is_none()
+            && self.thinker.is_none()
+            && position.legal_bits() != 0;
+        let analysed = self.analysis.as_ref().map(|(board, _)| *board);
+        if !wanted || analysed != Some(position) {
+            if let Some(analyser) = self.analyser.take() {
+                self.analysis_engine = analyser.cancel();
+            }
+            self.analysis = None;
+        }
+        if !wanted {
+            return;
+        }
+
+        if let Some(analyser) = self.analyser.as_mut() {
+            analyser.step(20);
+            let mut info = analyser.info();
+            let result = analyser.poll().map(|(engine, result)| {
+                self.analysis_engine = engine;
+                result
+            });
+            // The slice has reported nothing yet when it just started.
+            if info.iterations > 0 {
+                info.iterations += self.analysis_iterations;
+                self.analysis = Some((position, info));
+            }
+            match result {
+                Some(Ok(_)) => {
+                    self.analyser = None;
+                    self.analysis_iterations = self.current_iterations();
+                }
+                Some(Err(err)) => {
+                    self.analyser = None;
+                    self.analyse = false;
+                    self.analysis_error = Some(err.to_string());
+                }
+                None => {}
+            }
+            return;
+        }
+
+        // Settings saved by an older version may name any engine.
+        if self.analysis_ai.difficulty.is_some() || !self.analysis_ai.custom_engine.reports_moves()
+        {
+            self.analysis_ai = mode::AiSettings {
+                difficulty: None,
+                ..Default::default()
+            };
+        }
+        let spec = self.analysis_ai.engine_spec();
+        let (mut engine, fresh) = match self.analysis_engine.take() {
+            Some(engine) if self.analysis_spec == spec => (engine, false),
+            _ => (self.analysis_ai.create_engine(), true),
+        };
+        self.analysis_spec = spec;
+        // A new position, or a search that used up its budget, starts from
+        // an empty tree.
+        if fresh || self.analysis.is_none() || self.analysis_iterations >= ANALYSIS_ITERATIONS {
+            engine.new_game();
+            self.analysis_iterations = 0;
+        }
+        self.analyser = Some(thinker::Thinker::start(engine, position, ANALYSIS_SLICE));
+        self.analysis
+            .get_or_insert_with(|| (position, SearchInfo::default()));
+    }
+
+    /// Simulations shown for the analysis.
+    fn current_iterations(&self) -> i32 {
+        self.analysis
+            .as_ref()
+            .map_or(0, |(_, info)| info.iterations)
+    }
+
+    /// The search to draw on the board: the AI's while it thinks, or the
+    /// analysis.
+    fn current_analysis(&self) -> Option<SearchInfo> {
+        if !self.analyse || self.is_net_game() || self.editor.is_some() {
+            return None;
+        }
+        if let Some(thinker) = &self.thinker {
+            return Some(thinker.info());
+        }
+
+        self.analysis.as_ref().map(|(_, info)| info.clone())
+    }
+
+    /// Whether the AI plays the side to move and is not paused.
+    fn is_ai_to_move(&self) -> bool {
+        !self.ai_paused
+            && self.ply == self.game.moves.len()
+            && self.mode.is_ai(self.board.player)
+            && !self.board.is_game_ended()
+    }
+
+    /// Shows the local game at `ply` on the board.
+    fn sync_board(&mut self) {
+        self.board = to_board(&self.game.position_at(self.ply));
+    }
+
+    /// Plays `position`, or passes if it is 0, at the end of the game.
+    fn play_move(&mut self, position: u64) {
+        let before = self.game.position();
+        if let Err(err) = self.game.play(position) {
+            #[cfg(target_arch = "wasm32")]
+            log!("{}", err);
+            #[cfg(not(target_arch = "wasm32"))]
+            eprintln!("{}", err);
+            return;
+        }
+        self.ply = self.game.moves.len();
+        self.redo.clear();
+        self.sync_board();
+        if position != 0 {
+            let last_move = overlay::LastMove {
+                position,
+                flipped: before.flips(position),
+            };
+            self.animation = animation::Animation::new(last_move, self.animation_speed);
+        }
+    }
+
+    fn can_undo(&self) -> bool {
+        self.mode.allow_undo && self.mode.has_human() && !self.game.moves.is_empty()
+    }
+
+    /// Takes back moves up to the previous turn of a human, stopping the AI.
+    fn undo(&mut self) {
+        self.cancel_ai();
+        self.stop_pondering();
+        self.pending_move = None;
+        while let Some(position) = self.game.undo() {
+            self.redo.push(position);
+            if position != 0 && self.mode.is_human(self.game.position().player()) {
+                break;
+            }
+        }
+        self.ply = self.game.moves.len();
+        self.animation = None;
+        self.sync_board();
+    }
+
+    /// Plays the moves taken back by `undo` again.
+    fn redo(&mut self) {
+        self.cancel_ai();
+        self.pending_move = None;
+        while let Some(position) = self.redo.pop() {
+            if self.game.play(position).is_err() {
+                self.redo.clear();
+                break;
+            }
+            let board = self.game.position();
+            if self.mode.is_human(board.player()) && !game::is_legal(&board, 0) {
+                break;
+            }
+        }
+        self.ply = self.game.moves.len();
+        self.animation = None;
+        self.sync_board();
+    }
+
+    /// Plays `position` at the ply shown, dropping the later moves, which
+    /// are kept as a variation if `branch` is set.
+    fn play_from_here(&mut self, position: u64, branch: bool) {
+        if branch {
+            self.variations.push(self.game.clone());
+        }
+        self.game.moves.truncate(self.ply);
+        self.play_move(position);
+    }
+
+    /// Shows the game at `ply`, stopping the AI's search.
+    fn go_to(&mut self, ply: usize) {
+        self.cancel_ai();
+        self.pending_move = None;
+        self.ply = ply.min(self.game.moves.len());
+        self.animation = None;
+        self.sync_board();
+    }
+
+    fn keyboard_shortcuts(&mut self, ctx: &egui::CtxRef) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        let input = ctx.input();
+        if input.modifiers.command && self.mode.allow_undo {
+            if input.key_pressed(egui::Key::Z) && !input.modifiers.shift {
+                if self.can_undo() {
+                    self.undo();
+                }
+                return;
+            }
+            if input.key_pressed(egui::Key::Y)
+                || (input.key_pressed(egui::Key::Z) && input.modifiers.shift)
+            {
+                if !self.redo.is_empty() {
+                    self.redo();
+                }
+                return;
+            }
+        }
+        let ply = if input.key_pressed(egui::Key::ArrowLeft) {
+            Some(self.ply.saturating_sub(1))
+        } else if input.key_pressed(egui::Key::ArrowRight) {
+            Some(self.ply + 1)
+        } else if input.key_pressed(egui::Key::Home) {
+            Some(0)
+        } else if input.key_pressed(egui::Key::End) {
+            Some(self.game.moves.len())
+        } else {
+            None
+        };
+        if let Some(ply) = ply.filter(|&ply| ply != self.ply) {
+            self.go_to(ply);
+        }
+    }
+
+    /// The moves of the game, to jump to any of them, and the other lines.
+    fn moves_ui(&mut self, ui: &mut egui::Ui) {
+        let end = self.game.moves.len();
+        let mut ply = None;
+        ui.horizontal(|ui| {
+            for (text, target) in [
+                ("|<", 0),
+                ("<", self.ply.saturating_sub(1)),
+                (">", (self.ply + 1).min(end)),
+                (">|", end),
+            ] {
+                if ui
+                    .add_enabled(target != self.ply, egui::Button::new(text))
+                    .clicked()
+                {
+                    ply = Some(target);
+                }
+            }
+        });
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("Move list").show(ui, |ui| {
+                let mut board = self.game.start;
+                let mut number = 1;
+                if board.player() == 1 {
+                    ui.label("1.");
+                    ui.label("…");
+                }
+                for (i, &position) in self.game.moves.iter().enumerate() {
+                    if board.player() == 0 {
+                        ui.label(format!("{}.", number));
+                    }
+                    let name = if position == 0 {
+                        "pass".to_owned()
+                    } else {
+                        game::square_name(position)
+                    };
+                    if ui.selectable_label(self.ply == i + 1, name).clicked() {
+                        ply = Some(i + 1);
+                    }
+                    if board.player() == 1 {
+                        ui.end_row();
+                        number += 1;
+                    }
+                    board = board.play(position);
+                }
+            });
+
+            let mut switch = None;
+            for (i, line) in self.variations.iter().enumerate() {
+                let moves: Vec<String> = line.moves.iter().map(|&m| game::square_name(m)).collect();
+                let text = format!("Line {}: {} moves", i + 1, moves.len());
+                if ui.button(text).on_hover_text(moves.join(" ")).clicked() {
+                    switch = Some(i);
+                }
+            }
+            if let Some(i) = switch {
+                std::mem::swap(&mut self.game, &mut self.variations[i]);
+                self.redo.clear();
+                ply = Some(self.game.moves.len());
+            }
+        });
+
+        if let Some(ply) = ply {
+            self.go_to(ply);
+        }
+    }
+
+    /// Tells who passed last, and lets a human who has to pass do it.
+    fn pass_ui(&mut self, ui: &mut egui::Ui) {
+        if self.ply > 0 && self.game.moves[self.ply - 1] == 0 {
+            let player = self.game.position_at(self.ply - 1).player();
+            ui.label(format!(
+                "{} had no legal move and passed.",
+                colour_name(player)
+            ));
+        }
+        let board = self.game.position();
+        if self.ply == self.game.moves.len() && game::is_legal(&board, 0) {
+            let player = board.player();
+            ui.horizontal(|ui| {
+                ui.label(format!("{} must pass.", colour_name(player)));
+                if self.mode.is_human(player) && ui.button("Pass").clicked() {
+                    self.play_move(0);
+                }
+            });
+        }
+    }
+
+    /// The result of a finished game, and what to do next.
+    fn game_over_window(&mut self, ctx: &egui::CtxRef) {
+        let board = self.game.position();
+        if self.ply < self.game.moves.len() || !board.is_game_ended() || self.pending_move.is_some()
+        {
+            return;
+        }
+        let (black, white) = board.count();
+        let passes = self.game.moves.iter().filter(|&&m| m == 0).count();
+        let moves = self.game.moves.len() - passes;
+        let (mut rematch, mut swap, mut review) = (false, false, false);
+        egui::Window::new("Game over")
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.heading(match board.winner() {
+                    2 => "Draw".to_owned(),
+                    winner => format!(
+                        "{} wins by {}",
+                        colour_name(winner),
+                        (black as i32 - white as i32).abs()
+                    ),
+                });
+                ui.label(format!("Black {} – {} White", black, white));
+                ui.label(format!("{} moves, {} passes", moves, passes));
+                ui.horizontal(|ui| {
+                    rematch = ui.button("Rematch").clicked();
+                    swap = ui.button("Swap colours").clicked();
+                    review = ui.button("Review").clicked();
+                });
+                ui.separator();
+                self.save_ui(ui);
+            });
+
+        if swap {
+            let mode = self.played_mode.as_mut().unwrap_or(&mut self.mode);
+            mode.controllers.swap(0, 1);
+            mode.ai.swap(0, 1);
+            self.engines.swap(0, 1);
+            self.engine_specs.swap(0, 1);
+        }
+        if rematch || swap {
+            self.new_game(self.game.start);
+        } else if review {
+            self.go_to(0);
+        }
+    }
+
+    /// The game in GGF, with the players of the current mode.
+    fn ggf(&self) -> ggf::GgfGame {
+        let (black, white) = (self.mode.player_name(0), self.mode.player_name(1));
+        ggf::GgfGame::new(self.game.clone(), &black, &white)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.save_path);
+            if ui.button("Save").clicked() {
+                let path = self.save_path.trim();
+                let result = std::fs::write(path, format!("{}\n", self.ggf()));
+                self.save_status = Some(match result {
+                    Ok(()) => format!("Saved to {}", path),
+                    Err(err) => format!("Cannot save to {}: {}", path, err),
+                });
+            }
+        });
+        if let Some(status) = &self.save_status {
+            ui.label(status);
+        }
+    }
+
+    /// Files can't be written from the browser, so the game is shown to be
+    /// copied instead.
+    #[cfg(target_arch = "wasm32")]
+    fn save_ui(&mut self, ui: &mut egui::Ui) {
+        let mut text = self.ggf().to_string();
+        ui.label("Game record (GGF)");
+        ui.text_edit_multiline(&mut text);
+    }
+
+    /// Starts setting up a position from the one shown.
+    fn open_editor(&mut self) {
+        self.cancel_ai();
+        self.stop_pondering();
+        self.pending_move = None;
+        self.editor = Some(editor::Editor::new(&self.game.position_at(self.ply)));
+    }
+
+    /// The tools of the position editor, and the ways to leave it.
+    fn editor_window(&mut self, ctx: &egui::CtxRef) {
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let (mut play, mut analyse, mut cancel) = (false, false, false);
+        egui::Window::new("Position editor")
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    for tool in editor::Tool::ALL.iter() {
+                        ui.radio_value(&mut editor.tool, *tool, tool.name());
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut editor.player, 0, "Black to move");
+                    ui.radio_value(&mut editor.player, 1, "White to move");
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Clear").clicked() {
+                        editor.clear();
+                    }
+                    if ui.button("Initial").clicked() {
+                        editor.reset();
+                    }
+                });
+                let valid = match editor.validate() {
+                    Ok(_) => true,
+                    Err(err) => {
+                        ui.colored_label(egui::Color32::RED, err);
+                        false
+                    }
+                };
+                ui.horizontal(|ui| {
+                    play = ui.add_enabled(valid, egui::Button::new("Play")).clicked();
+                    analyse = ui
+                        .add_enabled(valid, egui::Button::new("Analyse"))
+                        .on_hover_text("Both sides are played by hand")
+                        .clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+
+        if cancel {
+            self.editor = None;
+        } else if let Some(Ok(start)) = (play || analyse).then(|| editor.validate()) {
+            self.new_game(start);
+            if analyse {
+                self.played_mode = Some(self.mode.clone());
+                self.mode.controllers = [mode::Controller::Human; 2];
+                self.analyse = true;
+            }
+        }
+    }
+
+    /// Asks what becomes of the later moves when one is made at an earlier
+    /// ply.
+    fn branch_window(&mut self, ctx: &egui::CtxRef) {
+        let position = match self.pending_move {
+            Some(position) => position,
+            None => return,
+        };
+        let mut choice = None;
+        egui::Window::new("Play from here")
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} replaces the {} later moves.",
+                    game::square_name(position),
+                    self.game.moves.len() - self.ply
+                ));
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("Branch")
+                        .on_hover_text("Keep them as another line")
+                        .clicked()
+                    {
+                        choice = Some(true);
+                    }
+                    if ui
+                        .button("Truncate")
+                        .on_hover_text("Discard them")
+                        .clicked()
+                    {
+                        choice = Some(false);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.pending_move = None;
+                    }
+                });
+            });
+        if let Some(branch) = choice {
+            self.pending_move = None;
+            self.play_from_here(position, branch);
+        }
+    }
+
+    /// Plays the move the AI's search ended with.
+    fn finish_ai(
+        &mut self,
+        engine: Option<Box<dyn Engine>>,
+        result: Result<u64, engine::EngineError>,
+    ) {
+        let side = self.board.player as usize;
+        let board = self.position();
+        let result = result.map_err(|err| err.to_string()).and_then(|position| {
+            if game::is_legal(&board, position) {
+                Ok(position)
+            } else {
+                let square = game::square_name(position);
+                Err(format!("the engine played an illegal move: {}", square))
+            }
+        });
+        let position = match result {
+            Ok(position) => position,
+            // The engine is created again when the AI is resumed.
+            Err(err) => {
+                self.ai_paused = true;
+                self.ai_error = Some(err);
+                return;
+            }
+        };
+        self.play_move(position);
+
+        let engine = match engine {
+            Some(engine) => engine,
+            None => return,
+        };
+        if self.ponder
+            && self.mode.is_human(self.board.player)
+            && !self.board.legal_moves().is_empty()
+        {
+            let state = board.play(position);
+            self.ponderer = Some((side, ponder::Ponderer::start(engine, state)));
+        } else {
+            self.engines[side] = Some(engine);
+        }
+    }
+
+    /// Stops the AI's search, if any, keeping its engine.
+    fn cancel_ai(&mut self) {
+        if let Some(thinker) = self.thinker.take() {
+            self.engines[self.board.player as usize] = thinker.cancel();
+        }
+    }
+
+    fn stop_pondering(&mut self) {
+        if let Some((side, ponderer)) = self.ponderer.take() {
+            self.engines[side] = Some(ponderer.finish());
+        }
+    }
+
+    /// Starts a game from `start`, with the mode played before any analysis.
+    fn new_game(&mut self, start: moai::BitBoard) {
+        self.cancel_ai();
+        self.stop_pondering();
+        if let Some(mode) = self.played_mode.take() {
+            self.mode = mode;
+        }
+        self.ai_paused = false;
+        self.ai_error = None;
+        self.editor = None;
+        self.game = game::Game::new(start);
         #[cfg(not(target_arch = "wasm32"))]
-        println!("{}", count);
-        let (mut x, mut y) = (0, 0);
-        for i in 0..64 {
-            if position & (1 << i) != 0 {
-                x = (63 - i) % 8;
-                y = (63 - i) / 8;
+        {
+            self.save_status = None;
+        }
+        self.redo.clear();
+        self.variations.clear();
+        self.go_to(0);
+        for engine in self.engines.iter_mut().flatten() {
+            engine.new_game();
+        }
+    }
+
+    /// The window choosing who plays each colour, open while `new_mode` is
+    /// set.
+    fn new_game_window(&mut self, ctx: &egui::CtxRef) {
+        let new_mode = match self.new_mode.as_mut() {
+            Some(new_mode) => new_mode,
+            None => return,
+        };
+        let (mut open, mut start) = (true, false);
+        egui::Window::new("New game")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                for (side, colour) in ["Black", "White"].iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(*colour);
+                        for controller in [mode::Controller::Human, mode::Controller::Ai] {
+                            ui.radio_value(
+                                &mut new_mode.controllers[side],
+                                controller,
+                                controller.name(),
+                            );
+                        }
+                    });
+                    if new_mode.controllers[side] == mode::Controller::Ai {
+                        ui.indent(side, |ui| ai_settings_ui(&mut new_mode.ai[side], false, ui));
+                    }
+                    ui.separator();
+                }
+                ui.checkbox(&mut new_mode.allow_undo, "Allow undo");
+                start = ui.button("Start").clicked();
+            });
+        if start {
+            self.played_mode = self.new_mode.take();
+            self.new_game(moai::BitBoard::initial());
+        } else if !open {
+            self.new_mode = None;
+        }
+    }
+
+    /// Whether the game is played against someone over the network.
+    fn is_net_game(&self) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.net.is_some() {
+            return true;
+        }
+        self.online.is_some()
+    }
+
+    fn play_net(&mut self, x: usize, y: usize) {
+        let position = 1 << (63 - (y * 8 + x));
+        if let Some(client) = self.online.as_mut() {
+            // The board is updated when the server accepts the move.
+            if client.is_my_turn() {
+                let square = game::square_name(position);
+                client.send(online::ClientMessage::Move { square });
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(net) = self.net.as_mut() {
+            match net.play(position) {
+                Ok(()) => {
+                    self.board = net.board().clone();
+                    self.net_error = None;
+                }
+                Err(err) => self.net_error = Some(err),
+            }
+        }
+    }
+
+    /// Restarts a LAN game. Online games are restarted with a rematch from
+    /// `online_ui`.
+    fn new_net_game(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(net) = self.net.as_mut() {
+            net.new_game();
+            self.board = net.board().clone();
+        }
+    }
+
+    fn online_ui(&mut self, ui: &mut egui::Ui) {
+        if let Some(client) = self.online.as_mut() {
+            match client.seat {
+                Some(seat) => ui.label(format!("Room {}, {}", client.room, seat_name(Some(seat)))),
+                None if client.closed => ui.label("Not connected"),
+                None => ui.label("Connecting..."),
+            };
+            if let Some(state) = client.state.as_ref() {
+                let clocks = client.clocks();
+                let player = |name: &Option<String>| name.clone().unwrap_or_else(|| "-".into());
+                ui.label(format!(
+                    "Black: {} {}",
+                    player(&state.black),
+                    clock_text(clocks[0])
+                ));
+                ui.label(format!(
+                    "White: {} {}",
+                    player(&state.white),
+                    clock_text(clocks[1])
+                ));
+                if !state.spectators.is_empty() {
+                    ui.label(format!("Watching: {}", state.spectators.join(", ")));
+                }
+                let status = match (&state.result, state.running) {
+                    (Some(result), _) => result.clone(),
+                    _ if client.is_my_turn() => "Your move".to_owned(),
+                    (None, Some(_)) => format!("{} to move", seat_name(state.running)),
+                    (None, None) => "Waiting for players".to_owned(),
+                };
+                ui.label(status);
+            }
+            if let Some(error) = client.error.as_ref() {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+            let playing = client.seat.and_then(online::Seat::player).is_some();
+            let over = client.state.as_ref().is_some_and(|s| s.result.is_some());
+            let mut leave = false;
+            ui.horizontal(|ui| {
+                if playing && !over && ui.button("Resign").clicked() {
+                    client.send(online::ClientMessage::Resign);
+                }
+                if playing && over && ui.button("Rematch").clicked() {
+                    client.send(online::ClientMessage::Rematch);
+                }
+                leave = ui.button("Leave").clicked();
+            });
+            if leave {
+                self.online = None;
+                self.board = Board::default();
+            }
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Server");
+            ui.text_edit_singleline(&mut self.online_url);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Room");
+            ui.text_edit_singleline(&mut self.online_room);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Name");
+            ui.text_edit_singleline(&mut self.online_name);
+        });
+        egui::ComboBox::from_label("Seat")
+            .selected_text(seat_name(self.online_seat))
+            .show_ui(ui, |ui| {
+                for seat in [
+                    None,
+                    Some(online::Seat::Black),
+                    Some(online::Seat::White),
+                    Some(online::Seat::Spectator),
+                ] {
+                    ui.selectable_value(&mut self.online_seat, seat, seat_name(seat));
+                }
+            });
+        if ui.button("Join").clicked() {
+            self.online = Some(online::client::OnlineClient::join(
+                self.online_url.trim(),
+                self.online_room.trim(),
+                self.online_name.trim(),
+                self.online_seat,
+            ));
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn net_ui(&mut self, ui: &mut egui::Ui) {
+        if let Some(err) = &self.net_error {
+            ui.colored_label(egui::Color32::RED, err);
+        }
+        if let Some(session) = self.net.as_mut() {
+            let colour = if session.colour == 0 {
+                "black"
+            } else {
+                "white"
+            };
+            let status = match session.status() {
+                net::Status::Listening(address) => format!("Waiting on port {}", address.port()),
+                net::Status::Connecting => "Connecting...".to_owned(),
+                net::Status::Connected if session.is_my_turn() => "Your move".to_owned(),
+                net::Status::Connected => "Opponent's move".to_owned(),
+                net::Status::Disconnected(reason) => format!("Disconnected: {}", reason),
+            };
+            ui.label(format!("Playing {}. {}", colour, status));
+            let mut leave = false;
+            ui.horizontal(|ui| {
+                let disconnected = matches!(session.status(), net::Status::Disconnected(_));
+                if !session.is_host() && disconnected && ui.button("Reconnect").clicked() {
+                    session.reconnect();
+                }
+                leave = ui.button("Leave").clicked();
+            });
+            if leave {
+                self.net = None;
+                self.net_error = None;
+                self.board = Board::default();
             }
+            return;
         }
 
-        self.board = self.board.play(x, y);
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut self.net_port).clamp_range(1024..=65535));
+            if ui.button("Host").clicked() {
+                match net::NetSession::host(self.net_port, 0) {
+                    Ok(session) => {
+                        self.board = session.board().clone();
+                        self.net = Some(session);
+                        self.net_error = None;
+                    }
+                    Err(err) => {
+                        let message = format!("Cannot host on port {}: {}", self.net_port, err);
+                        self.net_error = Some(message);
+                    }
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.net_address);
+            if ui.button("Join").clicked() {
+                let session = net::NetSession::join(self.net_address.trim());
+                self.board = session.board().clone();
+                self.net = Some(session);
+                self.net_error = None;
+            }
+        });
     }
-}
\ No newline at end of file
+}
+
+/// Settings of an engine playing a side, or of the analysis when
+/// `analysis` is set: it searches until stopped, so only engines reporting
+/// their moves are offered and neither levels nor time apply.
+fn ai_settings_ui(settings: &mut mode::AiSettings, analysis: bool, ui: &mut egui::Ui) {
+    let offered = |kind: &EngineKind| !analysis || kind.reports_moves();
+    let level_name = |difficulty: Option<difficulty::Difficulty>| match difficulty {
+        Some(difficulty) => difficulty.name(),
+        None => "Custom",
+    };
+    if !analysis {
+        egui::ComboBox::from_label("Level")
+            .selected_text(level_name(settings.difficulty))
+            .show_ui(ui, |ui| {
+                for difficulty in difficulty::Difficulty::ALL.iter() {
+                    ui.selectable_value(
+                        &mut settings.difficulty,
+                        Some(*difficulty),
+                        difficulty.name(),
+                    );
+                }
+                ui.selectable_value(&mut settings.difficulty, None, level_name(None));
+            });
+    }
+    if settings.difficulty.is_some() {
+        return;
+    }
+
+    egui::ComboBox::from_label("Engine")
+        .selected_text(settings.custom_engine.name())
+        .show_ui(ui, |ui| {
+            for kind in EngineKind::builtin().into_iter().filter(offered) {
+                let selected = settings.custom_engine.name() == kind.name();
+                if ui.selectable_label(selected, kind.name()).clicked() && !selected {
+                    settings.custom_engine = kind;
+                }
+            }
+            // Programs and files can't be opened from the browser.
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                let selected = matches!(settings.custom_engine, EngineKind::External(_));
+                if !analysis && ui.selectable_label(selected, "external").clicked() && !selected {
+                    settings.custom_engine = EngineKind::External(String::new());
+                }
+                let selected = matches!(settings.custom_engine, EngineKind::Pattern(_));
+                if ui.selectable_label(selected, "pattern").clicked() && !selected {
+                    settings.custom_engine = EngineKind::Pattern(String::new());
+                }
+                let selected = matches!(settings.custom_engine, EngineKind::Network(_));
+                if ui.selectable_label(selected, "network").clicked() && !selected {
+                    settings.custom_engine = EngineKind::Network(String::new());
+                }
+            }
+        });
+    if let EngineKind::External(command) = &mut settings.custom_engine {
+        ui.horizontal(|ui| {
+            ui.label("Command");
+            ui.text_edit_singleline(command)
+                .on_hover_text("An engine speaking the NBoard protocol, with its arguments");
+        });
+    }
+    if let EngineKind::Pattern(path) = &mut settings.custom_engine {
+        ui.horizontal(|ui| {
+            ui.label("Weights");
+            ui.text_edit_singleline(path)
+                .on_hover_text("A weights file written by the train tool");
+        });
+    }
+    if let EngineKind::Network(path) = &mut settings.custom_engine {
+        ui.horizontal(|ui| {
+            ui.label("Network");
+            ui.text_edit_singleline(path)
+                .on_hover_text("A network file written by the nettrain tool");
+        });
+    }
+    if !analysis {
+        ui.add(egui::Slider::new(&mut settings.think_time, 100..=5000).text("ms per move"));
+    }
+    let params = match &mut settings.custom_engine {
+        EngineKind::Mcts(params) => params,
+        _ => return,
+    };
+
+    egui::ComboBox::from_label("Selection")
+        .selected_text(params.selection.name())
+        .show_ui(ui, |ui| {
+            for selection in moai::SelectionPolicy::ALL.iter() {
+                ui.selectable_value(&mut params.selection, *selection, selection.name());
+            }
+        });
+    egui::ComboBox::from_label("Final move")
+        .selected_text(params.final_move.name())
+        .show_ui(ui, |ui| {
+            for final_move in moai::FinalMovePolicy::ALL.iter() {
+                ui.selectable_value(&mut params.final_move, *final_move, final_move.name());
+            }
+        });
+    ui.add(egui::Slider::new(&mut params.cp, 0.0..=4.0).text("cp"));
+    ui.add(egui::Slider::new(&mut params.playout, 1..=16).text("playouts"));
+    if ui.button("Defaults").clicked() {
+        *params = moai::SearchParams::default();
+    }
+}
+
+fn seat_name(seat: Option<online::Seat>) -> &'static str {
+    match seat {
+        None => "any colour",
+        Some(online::Seat::Black) => "black",
+        Some(online::Seat::White) => "white",
+        Some(online::Seat::Spectator) => "spectator",
+    }
+}
+
+/// Share of the points expected from `score`, a result from -1 to 1.
+fn win_rate(score: f64) -> f64 {
+    ((score + 1.0) / 2.0).clamp(0.0, 1.0)
+}
+
+/// Colours the square of a move from red to green by its score, with its win
+/// rate in percent, and outlines it if it is the best.
+fn heat_square(painter: &egui::Painter, rect: egui::Rect, score: f64, best: bool) {
+    let rate = win_rate(score) as f32;
+    let (red, green) = ((2.0 - 2.0 * rate).min(1.0), (2.0 * rate).min(1.0));
+    let color =
+        egui::Color32::from_rgba_unmultiplied((red * 255.0) as u8, (green * 255.0) as u8, 0, 160);
+    painter.rect_filled(rect.shrink(1.0), 0.0, color);
+    painter.text(
+        rect.center(),
+        egui::Align2::CENTER_CENTER,
+        format!("{:.0}", rate * 100.0),
+        egui::TextStyle::Small,
+        egui::Color32::BLACK,
+    );
+    if best {
+        let stroke = egui::Stroke::new(2.0, egui::Color32::BLUE);
+        painter.rect_stroke(rect.shrink(2.0), 0.0, stroke);
+    }
+}
+
+fn colour_name(player: i32) -> &'static str {
+    if player == 0 {
+        "Black"
+    } else {
+        "White"
+    }
+}
+
+/// Minutes and seconds, such as `4:05`.
+fn clock_text(millis: u64) -> String {
+    let seconds = millis.div_ceil(1000);
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// The GUI board for `board`.
+fn to_board(board: &moai::BitBoard) -> Board {
+    let (black, white) = board.bits();
+    let mut result = Board::default();
+    for y in 0..8 {
+        for x in 0..8 {
+            let bit = 1u64 << (63 - (y * 8 + x));
+            result.disks[y][x] = if black & bit != 0 {
+                Disk::Black
+            } else if white & bit != 0 {
+                Disk::White
+            } else {
+                Disk::Empty
+            };
+        }
+    }
+    result.player = board.player();
+
+    result
+}
@@ -0,0 +1,341 @@
+//! Engine analysis as JSON, for tools that cannot link the crate. The
+//! requests are answered by `handle`; `src/bin/analysis.rs` serves it over
+//! HTTP.
+//!
+//! Every request names a position by an optional `board`, in the GGF `BO`
+//! format (the initial position by default), and `moves` played from it,
+//! such as `["f5", "d6"]`. Passes may be left out of `moves`.
+//!
+//! * `POST /analyze`: searches the position with `engine` (an
+//!   `EngineKind::parse` spec, MCTS by default) for `time_ms` or
+//!   `iterations` and returns the root moves ranked by visits, the PV and
+//!   the score, from -1 to 1 for the side to move.
+//! * `POST /solve`: the exact final disk difference for the side to move,
+//!   with the best move and perfect play to the end.
+//! * `POST /validate-game`: whether `moves` are legal, and the position
+//!   they lead to.
+
+use super::engine::{EngineKind, Limits, SearchInfo};
+use super::game::{self, parse_square, square_name, Game};
+use super::ggf;
+use super::moai::BitBoard;
+use serde::{Deserialize, Serialize};
+
+/// Limits on what a request may ask for.
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// Milliseconds of search at most.
+    pub max_time: u128,
+    pub max_iterations: i32,
+    /// Random playouts per MCTS simulation; the clock is only checked
+    /// between simulations.
+    pub max_playout: i32,
+    /// Positions the solver may search.
+    pub max_nodes: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_time: 10_000,
+            max_iterations: 10_000_000,
+            max_playout: 100,
+            max_nodes: 100_000_000,
+        }
+    }
+}
+
+/// Search time when a request gives no limits.
+const DEFAULT_TIME: u128 = 1000;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Request {
+    board: Option<String>,
+    moves: Vec<String>,
+    engine: Option<String>,
+    time_ms: Option<u128>,
+    iterations: Option<i32>,
+    max_nodes: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct MoveAnalysis {
+    #[serde(rename = "move")]
+    position: String,
+    visits: usize,
+    score: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct Analysis {
+    best_move: String,
+    score: f64,
+    pv: Vec<String>,
+    moves: Vec<MoveAnalysis>,
+    iterations: i32,
+    elapsed_ms: u128,
+}
+
+#[derive(Debug, Serialize)]
+struct Solved {
+    best_move: String,
+    score: i32,
+    pv: Vec<String>,
+    nodes: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct InvalidMove {
+    ply: usize,
+    message: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Validation {
+    valid: bool,
+    /// The first move that could not be played.
+    error: Option<InvalidMove>,
+    /// Moves played, passes included.
+    moves: Vec<String>,
+    board: String,
+    black: u32,
+    white: u32,
+    game_over: bool,
+}
+
+/// A response status and its JSON body.
+pub type Response = (u16, String);
+
+fn error(status: u16, message: &str) -> Response {
+    let body = serde_json::json!({ "error": message });
+    (status, body.to_string())
+}
+
+fn names(moves: &[u64]) -> Vec<String> {
+    moves.iter().map(|&m| square_name(m)).collect()
+}
+
+/// Plays `position`, first passing if the side to move has to.
+fn play(game: &mut Game, position: u64) -> Result<(), String> {
+    if position != 0 && game::is_legal(&game.position(), 0) {
+        game.moves.push(0);
+    }
+    game.play(position)
+}
+
+/// The game of a request: its start and the moves played, up to the first
+/// one that fails with the reason.
+fn game(request: &Request) -> Result<(Game, Option<InvalidMove>), String> {
+    let start = match request.board.as_deref() {
+        Some(board) => ggf::parse_board(board)?,
+        None => BitBoard::initial(),
+    };
+    let mut game = Game::new(start);
+    for (ply, name) in request.moves.iter().enumerate() {
+        let result = parse_square(name)
+            .ok_or_else(|| format!("bad move: {}", name))
+            .and_then(|position| play(&mut game, position));
+        if let Err(message) = result {
+            return Ok((game, Some(InvalidMove { ply, message })));
+        }
+    }
+
+    Ok((game, None))
+}
+
+/// The position of a request for a search, which must be valid and not
+/// finished.
+fn position(request: &Request) -> Result<BitBoard, Response> {
+    let (game, invalid) = game(request).map_err(|err| error(400, &err))?;
+    if let Some(invalid) = invalid {
+        let message = format!("move {}: {}", invalid.ply + 1, invalid.message);
+        return Err(error(422, &message));
+    }
+    let board = game.position();
+    if board.is_game_ended() {
+        return Err(error(422, "the game is over"));
+    }
+
+    Ok(board)
+}
+
+fn analyze(config: &Config, request: &Request) -> Result<Analysis, Response> {
+    let board = position(request)?;
+    let kind = match request.engine.as_deref() {
+        Some(spec) => EngineKind::parse(spec).map_err(|err| error(400, &err))?,
+        None => EngineKind::Mcts(Default::default()),
+    };
+    match kind {
+        EngineKind::External(_) => return Err(error(400, "external engines are not allowed")),
+        // It would read any file the service can.
+        EngineKind::Pattern(_) | EngineKind::Network(_) => {
+            return Err(error(400, "engines loading files are not allowed"))
+        }
+        EngineKind::Mcts(params) if params.playout > config.max_playout => {
+            let message = format!("playout may be at most {}", config.max_playout);
+            return Err(error(400, &message));
+        }
+        _ => {}
+    }
+    let limits = match (request.time_ms, request.iterations) {
+        (None, None) => Limits::time(DEFAULT_TIME),
+        (time, iterations) => Limits {
+            time: time.map(|time| time.min(config.max_time)),
+            iterations: iterations.map(|n| n.clamp(1, config.max_iterations)),
+        },
+    };
+    // Never search without a time limit.
+    let limits = Limits {
+        time: Some(limits.time.unwrap_or(config.max_time)),
+        ..limits
+    };
+
+    if board.legal_bits() == 0 {
+        return Ok(Analysis {
+            best_move: square_name(0),
+            score: 0.0,
+            pv: vec![square_name(0)],
+            moves: Vec::new(),
+            iterations: 0,
+            elapsed_ms: 0,
+        });
+    }
+    let mut engine = kind.create();
+    engine.set_position(board);
+    let mut info = SearchInfo::default();
+    let best_move = engine
+        .think(limits, &mut |i| info = i.clone())
+        .map_err(|err| error(500, &err.to_string()))?;
+    let mut moves = info.moves.clone();
+    moves.sort_by(|a, b| b.visits.cmp(&a.visits).then(b.score.total_cmp(&a.score)));
+
+    Ok(Analysis {
+        best_move: square_name(best_move),
+        score: info.score,
+        pv: names(&info.pv),
+        moves: moves
+            .iter()
+            .map(|stat| MoveAnalysis {
+                position: square_name(stat.position),
+                visits: stat.visits,
+                score: stat.score,
+            })
+            .collect(),
+        iterations: info.iterations,
+        elapsed_ms: info.elapsed,
+    })
+}
+
+fn solve(config: &Config, request: &Request) -> Result<Solved, Response> {
+    let board = position(request)?;
+    let max_nodes = request
+        .max_nodes
+        .map_or(config.max_nodes, |n| n.min(config.max_nodes));
+    let solution = board.solve(max_nodes).ok_or_else(|| {
+        let message = format!("not solved within {} nodes", max_nodes);
+        error(422, &message)
+    })?;
+
+    Ok(Solved {
+        best_move: square_name(solution.best_move),
+        score: solution.score,
+        pv: names(&solution.pv),
+        nodes: solution.nodes,
+    })
+}
+
+fn validate(request: &Request) -> Result<Validation, Response> {
+    let (game, error_move) = game(request).map_err(|err| error(400, &err))?;
+    let board = game.position();
+    let (black, white) = board.count();
+
+    Ok(Validation {
+        valid: error_move.is_none(),
+        error: error_move,
+        moves: names(&game.moves),
+        board: ggf::board_text(&board),
+        black,
+        white,
+        game_over: board.is_game_ended(),
+    })
+}
+
+fn to_json<T: Serialize>(result: Result<T, Response>) -> Response {
+    match result {
+        Ok(value) => (200, serde_json::to_string(&value).unwrap()),
+        Err(response) => response,
+    }
+}
+
+/// Answers a request for `path` with a JSON `body`.
+pub fn handle(config: &Config, method: &str, path: &str, body: &str) -> Response {
+    let path = path.split('?').next().unwrap_or("");
+    if !matches!(path, "/analyze" | "/solve" | "/validate-game") {
+        return error(404, "not found");
+    }
+    if method != "POST" {
+        return error(405, "use POST");
+    }
+    let request: Request = match serde_json::from_str(body) {
+        Ok(request) => request,
+        Err(err) => return error(400, &err.to_string()),
+    };
+    match path {
+        "/analyze" => to_json(analyze(config, &request)),
+        "/solve" => to_json(solve(config, &request)),
+        _ => to_json(validate(&request)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn post(path: &str, body: &str) -> (u16, Value) {
+        let (status, body) = handle(&Config::default(), "POST", path, body);
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    #[test]
+    fn analyzes_positions() {
+        let (status, body) = post("/analyze", r#"{"moves": ["f5"], "iterations": 500}"#);
+        assert_eq!(status, 200);
+        let moves = body["moves"].as_array().unwrap();
+        assert_eq!(moves.len(), 3);
+        assert_eq!(body["best_move"], body["pv"][0]);
+        let (status, _) = post("/analyze", r#"{"engine": "external:/bin/sh"}"#);
+        assert_eq!(status, 400);
+        let body = r#"{"engine": "mcts:playout=2000000000", "time_ms": 1}"#;
+        assert_eq!(post("/analyze", body).0, 400);
+        assert_eq!(post("/analyze", r#"{"engine": "mcts:cp=NaN"}"#).0, 400);
+        let (status, _) = handle(&Config::default(), "GET", "/analyze", "");
+        assert_eq!(status, 405);
+    }
+
+    #[test]
+    fn solves_endgames() {
+        // Black to move, with only g8 and h8 empty.
+        let board = format!("8 {}{}-- *", "*".repeat(57), "O".repeat(5));
+        let (status, body) = post("/solve", &format!(r#"{{"board": "{}"}}"#, board));
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body["best_move"], "g8");
+        assert_eq!(body["score"], 64);
+        let (status, _) = post("/solve", r#"{"max_nodes": 1000}"#);
+        assert_eq!(status, 422);
+    }
+
+    #[test]
+    fn validates_games() {
+        let (status, body) = post("/validate-game", r#"{"moves": ["f5", "d6", "c3"]}"#);
+        assert_eq!(status, 200);
+        assert_eq!(body["valid"], true);
+        assert_eq!(body["black"], 5);
+        let (_, body) = post("/validate-game", r#"{"moves": ["f5", "f5"]}"#);
+        assert_eq!(body["valid"], false);
+        assert_eq!(body["error"]["ply"], 1);
+        let (status, _) = post("/validate-game", r#"{"moves": [], "bogus": 1}"#);
+        assert_eq!(status, 400);
+    }
+}
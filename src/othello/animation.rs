@@ -0,0 +1,170 @@
+//! Animating moves on the GUI board: the placed disk grows, then the
+//! flipped disks turn over one after the other, outwards along each line.
+
+use super::overlay::LastMove;
+use wasm_timer::Instant;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "persistence", derive(serde::Deserialize, serde::Serialize))]
+pub enum Speed {
+    Off,
+    Slow,
+    #[default]
+    Normal,
+    Fast,
+}
+
+impl Speed {
+    pub const ALL: [Speed; 4] = [Speed::Off, Speed::Slow, Speed::Normal, Speed::Fast];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Speed::Off => "Off",
+            Speed::Slow => "Slow",
+            Speed::Normal => "Normal",
+            Speed::Fast => "Fast",
+        }
+    }
+
+    /// Milliseconds to place a disk or turn one over.
+    fn step(&self) -> u128 {
+        match self {
+            Speed::Off => 0,
+            Speed::Slow => 250,
+            Speed::Normal => 120,
+            Speed::Fast => 50,
+        }
+    }
+}
+
+/// How to draw an animated disk.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DiskFrame {
+    /// Share of the full radius.
+    pub scale: f32,
+    /// Whether the disk still shows the colour it had before the move.
+    pub before: bool,
+}
+
+pub struct Animation {
+    start: Instant,
+    last_move: LastMove,
+    step: u128,
+}
+
+impl Animation {
+    /// Starts animating `last_move`, unless animations are off.
+    pub fn new(last_move: LastMove, speed: Speed) -> Option<Self> {
+        if speed == Speed::Off {
+            return None;
+        }
+
+        Some(Self {
+            start: Instant::now(),
+            last_move,
+            step: speed.step(),
+        })
+    }
+
+    /// Milliseconds since the animation started.
+    pub fn elapsed(&self) -> u128 {
+        self.start.elapsed().as_millis()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.duration() <= self.elapsed()
+    }
+
+    fn duration(&self) -> u128 {
+        let flipped = self.last_move.flipped;
+        let farthest = (0..64)
+            .filter(|i| flipped & (1 << i) != 0)
+            .map(|i| self.distance(1 << i))
+            .max()
+            .unwrap_or(0);
+
+        (farthest as u128 + 1) * self.step
+    }
+
+    /// Squares from the placed disk to `bit`, along its line.
+    fn distance(&self, bit: u64) -> u32 {
+        let (from, to) = (
+            63 - self.last_move.position.trailing_zeros(),
+            63 - bit.trailing_zeros(),
+        );
+        let dx = (from % 8).abs_diff(to % 8);
+        let dy = (from / 8).abs_diff(to / 8);
+
+        dx.max(dy)
+    }
+
+    /// The disk at `bit`, `elapsed` milliseconds in, or `None` if it is
+    /// drawn as usual.
+    pub fn frame(&self, bit: u64, elapsed: u128) -> Option<DiskFrame> {
+        let progress = |start: u128| {
+            let t = elapsed.saturating_sub(start) as f32 / self.step as f32;
+            t.min(1.0)
+        };
+        if bit == self.last_move.position {
+            let t = progress(0);
+            return (t < 1.0).then_some(DiskFrame {
+                scale: t,
+                before: false,
+            });
+        }
+        if self.last_move.flipped & bit == 0 {
+            return None;
+        }
+
+        // Shrinks showing the old colour, then grows showing the new one.
+        let t = progress(self.distance(bit) as u128 * self.step);
+        (t < 1.0).then_some(DiskFrame {
+            scale: (2.0 * t - 1.0).abs(),
+            before: t < 0.5,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::othello::game::parse_square;
+    use crate::othello::moai::BitBoard;
+
+    #[test]
+    fn flips_follow_the_placed_disk_outwards() {
+        // Black at c4 flips d4, e4 and f4 along the row.
+        let square = |name| parse_square(name).unwrap();
+        let (flipped, placed) = (square("d4") | square("e4") | square("f4"), square("c4"));
+        let white = flipped;
+        let black = square("g4");
+        let board = BitBoard::from_bits(black, white, 0);
+        assert_eq!(board.flips(placed), flipped);
+
+        let last_move = LastMove {
+            position: placed,
+            flipped,
+        };
+        assert!(Animation::new(last_move, Speed::Off).is_none());
+        let animation = Animation::new(last_move, Speed::Normal).unwrap();
+        let step = Speed::Normal.step();
+        assert_eq!(animation.duration(), 4 * step);
+
+        let placing = animation.frame(placed, step / 2).unwrap();
+        assert!(!placing.before && placing.scale > 0.0 && placing.scale < 1.0);
+        assert_eq!(animation.frame(placed, step), None);
+        // d4 turns over during the second step, e4 not yet.
+        let turning = animation.frame(square("d4"), step + step / 4).unwrap();
+        assert!(turning.before);
+        assert_eq!(
+            animation.frame(square("e4"), step + step / 4).unwrap(),
+            DiskFrame {
+                scale: 1.0,
+                before: true
+            }
+        );
+        assert!(!animation.frame(square("d4"), 2 * step - 1).unwrap().before);
+        assert_eq!(animation.frame(square("d4"), 2 * step), None);
+        assert_eq!(animation.frame(black, 0), None);
+    }
+}
@@ -0,0 +1,174 @@
+//! Engine-vs-engine games, used for tuning and calibrating the AI.
+
+use super::engine::{Engine, Limits};
+use super::game::{self, Game};
+use super::moai::BitBoard;
+
+/// A finished game. Passes are recorded as a `0` move.
+#[derive(Clone, Debug)]
+pub struct GameRecord {
+    pub start: BitBoard,
+    pub moves: Vec<u64>,
+    pub black: u32,
+    pub white: u32,
+    /// The player that lost by failing to move (an engine error or an
+    /// illegal move) and why. The disk counts are those of the last position.
+    pub forfeit: Option<(i32, String)>,
+}
+
+impl GameRecord {
+    pub fn game(&self) -> Game {
+        Game {
+            start: self.start,
+            moves: self.moves.clone(),
+        }
+    }
+
+    /// 0 if black won, 1 if white won and 2 for a draw.
+    pub fn winner(&self) -> i32 {
+        if let Some((player, _)) = self.forfeit {
+            1 - player
+        } else if self.black > self.white {
+            0
+        } else if self.white > self.black {
+            1
+        } else {
+            2
+        }
+    }
+}
+
+/// Plays a game from `start` to the end. Each player is asked for a move
+/// only when it has a legal one; passes are played automatically.
+pub fn play_game<B, W>(start: BitBoard, mut black: B, mut white: W) -> GameRecord
+where
+    B: FnMut(&BitBoard) -> u64,
+    W: FnMut(&BitBoard) -> u64,
+{
+    let mut board = start;
+    let mut moves = Vec::new();
+    while !board.is_game_ended() {
+        let position = if board.legal_bits() == 0 {
+            0
+        } else if board.player() == 0 {
+            black(&board)
+        } else {
+            white(&board)
+        };
+        board = board.play(position);
+        moves.push(position);
+    }
+
+    let (black, white) = board.count();
+    GameRecord {
+        start,
+        moves,
+        black,
+        white,
+        forfeit: None,
+    }
+}
+
+/// Plays a game between two engines from `start`, giving each `limits` per
+/// move. Engines are asked for a move only when they have a legal one. An
+/// engine that fails or answers an illegal move loses by forfeit.
+pub fn play_engines(
+    start: BitBoard,
+    black: &mut dyn Engine,
+    white: &mut dyn Engine,
+    limits: Limits,
+) -> GameRecord {
+    black.new_game();
+    white.new_game();
+    let mut board = start;
+    let mut moves = Vec::new();
+    let mut forfeit = None;
+    while !board.is_game_ended() {
+        let position = if board.legal_bits() == 0 {
+            0
+        } else {
+            let engine: &mut dyn Engine = if board.player() == 0 {
+                &mut *black
+            } else {
+                &mut *white
+            };
+            engine.set_position(board);
+            match engine.think(limits, &mut |_| {}) {
+                Ok(position) if game::is_legal(&board, position) => position,
+                Ok(position) => {
+                    let reason = format!("illegal move {}", game::square_name(position));
+                    forfeit = Some((board.player(), reason));
+                    break;
+                }
+                Err(err) => {
+                    forfeit = Some((board.player(), err.to_string()));
+                    break;
+                }
+            }
+        };
+        board = board.play(position);
+        moves.push(position);
+    }
+
+    let (black, white) = board.count();
+    GameRecord {
+        start,
+        moves,
+        black,
+        white,
+        forfeit,
+    }
+}
+
+/// Win/draw/loss tally from the point of view of one player.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Score {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Score {
+    /// Records a game in which the player had the colour `player`.
+    pub fn add(&mut self, record: &GameRecord, player: i32) {
+        match record.winner() {
+            2 => self.draws += 1,
+            w if w == player => self.wins += 1,
+            _ => self.losses += 1,
+        }
+    }
+
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Fraction of the available points scored, counting a draw as half a win.
+    pub fn ratio(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.5;
+        }
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+}
+
+/// Plays `games` games between `a` and `b` from the initial position,
+/// alternating colours, and returns the score of `a`.
+pub fn play_match<A, B>(games: u32, mut a: A, mut b: B) -> Score
+where
+    A: FnMut(&BitBoard) -> u64,
+    B: FnMut(&BitBoard) -> u64,
+{
+    let mut score = Score::default();
+    for game in 0..games {
+        let start = BitBoard::initial();
+        if game % 2 == 0 {
+            let record = play_game(start, &mut a, &mut b);
+            score.add(&record, 0);
+        } else {
+            let record = play_game(start, &mut b, &mut a);
+            score.add(&record, 1);
+        }
+    }
+
+    score
+}
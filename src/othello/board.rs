@@ -28,15 +28,7 @@ pub struct Board {
 
 impl Default for Board {
     fn default() -> Self {
-        let mut disks = Vec::new();
-        for y in 0..8 {
-            let mut column = Vec::new();
-            for x in 0..8 {
-                column.push(Disk::Empty);
-            }
-
-            disks.push(column);
-        }
+        let mut disks = vec![vec![Disk::Empty; 8]; 8];
 
         disks[3][3] = Disk::White;
         disks[3][4] = Disk::Black;
@@ -53,24 +45,17 @@ impl Default for Board {
 }
 
 pub fn coordinate(pos: &str) -> (usize, usize) {
-    let ch = pos.chars().nth(0).unwrap() as u8;
-    let x = ch - 'a' as u8;
+    let ch = pos.chars().next().unwrap() as u8;
+    let x = ch - b'a';
     let ch = pos.chars().nth(1).unwrap() as u8;
-    let y = ch - '1' as u8;
+    let y = ch - b'1';
 
     (x as usize, y as usize)
 }
 
 impl Board {
     pub fn new(width: usize, height: usize, player: i32) -> Self {
-        let mut disks = Vec::new();
-        for i in 0..height {
-            let mut column = Vec::new();
-            for j in 0..width {
-                column.push(Disk::Empty);
-            }
-            disks.push(column);
-        }
+        let disks = vec![vec![Disk::Empty; width]; height];
         Self {
             width,
             height,
@@ -84,18 +69,17 @@ impl Board {
         let width = board[0].len();
 
         let mut disks = Vec::new();
-        for y in 0..height {
+        for row in board.iter() {
             let mut column = Vec::new();
-            let chars: Vec<char> = board[y].chars().collect();
-            for x in 0..width {
-                if chars[x] == '.' {
+            for ch in row.chars() {
+                if ch == '.' {
                     column.push(Disk::Empty);
-                } else if chars[x] == '0' {
+                } else if ch == '0' {
                     column.push(Disk::Black);
-                } else if chars[x] == '1' {
+                } else if ch == '1' {
                     column.push(Disk::White);
                 } else {
-                    panic!("unknown char: {}", chars[x]);
+                    panic!("unknown char: {}", ch);
                 }
             }
             disks.push(column);
@@ -175,7 +159,7 @@ impl Board {
             }
         }
 
-        return true;
+        true
     }
 
     fn can_turn_over(
@@ -191,11 +175,11 @@ impl Board {
             return 0;
         }
         if self.disks[y as usize][x as usize] == disk {
-            return count;
+            count
         } else if self.disks[y as usize][x as usize] == disk.opponent() {
-            return self.can_turn_over(disk, x + inc_x, y + inc_y, inc_x, inc_y, count + 1);
+            self.can_turn_over(disk, x + inc_x, y + inc_y, inc_x, inc_y, count + 1)
         } else {
-            return 0;
+            0
         }
     }
 
@@ -245,6 +229,27 @@ impl Board {
         next_board
     }
 
+    /// Counts the positions `depth` plies ahead, like `BitBoard::perft`.
+    pub fn perft(&self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.legal_moves();
+        if moves.is_empty() {
+            let mut passed = self.clone();
+            passed.player = passed.next_player();
+            if passed.legal_moves().is_empty() {
+                return 1;
+            }
+            return passed.perft(depth - 1);
+        }
+
+        moves
+            .iter()
+            .map(|&(x, y)| self.play(x, y).perft(depth - 1))
+            .sum()
+    }
+
     pub fn num_disk(&self) -> (i32, i32) {
         let mut white = 0;
         let mut black = 0;
@@ -261,7 +266,7 @@ impl Board {
         (black, white)
     }
 
-    fn winner(&self) -> i32 {
+    pub fn winner(&self) -> i32 {
         let (black, white) = self.num_disk();
 
         // println!("white: {}, black: {}", white, black);
@@ -293,4 +298,26 @@ impl Board {
 
         res
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn perft_matches_published_counts() {
+        let expected = [1, 4, 12, 56, 244, 1396, 8200];
+        let board = Board::default();
+        for (depth, count) in expected.iter().enumerate() {
+            assert_eq!(board.perft(depth as u32), *count, "depth {}", depth);
+        }
+    }
+
+    #[test]
+    fn round_trips_through_strings() {
+        let board = Board::default().play(5, 4);
+        let copy = Board::from_strings(board.to_strings(), board.player);
+        assert_eq!(copy.disks, board.disks);
+        assert_eq!(copy.player, 1);
+    }
+}
@@ -0,0 +1,179 @@
+//! Named strength levels for the AI.
+
+use super::arena::{self, Score};
+use super::engine::baseline::Randomized;
+use super::engine::{Engine, EngineKind, Limits};
+use super::moai::{BitBoard, SearchParams};
+
+/// Upper bound on thinking time for every level, in milliseconds.
+const TIME_LIMIT: u128 = 5000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "persistence", derive(serde::Deserialize, serde::Serialize))]
+pub enum Difficulty {
+    Beginner,
+    Novice,
+    Intermediate,
+    Advanced,
+    Expert,
+}
+
+#[derive(Clone, Debug)]
+pub struct Level {
+    pub engine: EngineKind,
+    pub limits: Limits,
+    /// Probability of playing a uniformly random legal move instead.
+    pub randomness: f64,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 5] = [
+        Difficulty::Beginner,
+        Difficulty::Novice,
+        Difficulty::Intermediate,
+        Difficulty::Advanced,
+        Difficulty::Expert,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Beginner => "Beginner",
+            Difficulty::Novice => "Novice",
+            Difficulty::Intermediate => "Intermediate",
+            Difficulty::Advanced => "Advanced",
+            Difficulty::Expert => "Expert",
+        }
+    }
+
+    pub fn level(&self) -> Level {
+        let mcts = |iterations| Level {
+            engine: EngineKind::Mcts(SearchParams::default()),
+            limits: Limits {
+                time: Some(TIME_LIMIT),
+                iterations: Some(iterations),
+            },
+            randomness: 0.0,
+        };
+        match self {
+            Difficulty::Beginner => Level {
+                engine: EngineKind::Greedy,
+                limits: Limits::default(),
+                randomness: 0.5,
+            },
+            Difficulty::Novice => Level {
+                randomness: 0.2,
+                ..mcts(100)
+            },
+            Difficulty::Intermediate => Level {
+                randomness: 0.05,
+                ..mcts(500)
+            },
+            Difficulty::Advanced => mcts(2000),
+            Difficulty::Expert => mcts(30000),
+        }
+    }
+
+    /// An engine playing at this level, to be used with `limits`.
+    pub fn engine(&self) -> Box<dyn Engine> {
+        let level = self.level();
+        let engine = level.engine.create();
+        if level.randomness > 0.0 {
+            Box::new(Randomized::new(engine, level.randomness).unwrap())
+        } else {
+            engine
+        }
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.level().limits
+    }
+
+    /// Picks a move for the side to move with a fresh engine, or the first
+    /// legal move if the engine fails.
+    pub fn choose_move(&self, board: &BitBoard) -> u64 {
+        let mut engine = self.engine();
+        engine.set_position(*board);
+        engine
+            .think(self.limits(), &mut |_| {})
+            .unwrap_or_else(|_| board.moves().first().copied().unwrap_or(0))
+    }
+}
+
+/// Elo difference corresponding to the score ratio `ratio`, clamped to
+/// +-800 for whitewashes.
+pub fn elo_difference(ratio: f64) -> f64 {
+    let ratio = ratio.clamp(0.01, 0.99);
+    -400.0 * f64::log10(1.0 / ratio - 1.0)
+}
+
+/// Result of a match between two adjacent levels.
+pub struct Calibration {
+    pub stronger: Difficulty,
+    pub weaker: Difficulty,
+    /// Score of the stronger level.
+    pub score: Score,
+}
+
+impl Calibration {
+    pub fn elo(&self) -> f64 {
+        elo_difference(self.score.ratio())
+    }
+
+    pub fn is_monotonic(&self) -> bool {
+        self.score.ratio() > 0.5
+    }
+}
+
+/// Plays every level against the one below it for `games` games and calls
+/// `report` with each result as soon as it is known.
+pub fn calibrate<F>(games: u32, mut report: F) -> Vec<Calibration>
+where
+    F: FnMut(&Calibration),
+{
+    let mut results = Vec::new();
+    for pair in Difficulty::ALL.windows(2) {
+        let (weaker, stronger) = (pair[0], pair[1]);
+        let score = arena::play_match(
+            games,
+            |board: &BitBoard| stronger.choose_move(board),
+            |board: &BitBoard| weaker.choose_move(board),
+        );
+        let calibration = Calibration {
+            stronger,
+            weaker,
+            score,
+        };
+        report(&calibration);
+        results.push(calibration);
+    }
+
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::othello::game;
+
+    #[test]
+    fn every_level_plays_a_legal_move() {
+        let board = BitBoard::initial();
+        let board = board.play(board.moves()[0]);
+        for difficulty in Difficulty::ALL.iter() {
+            let position = difficulty.choose_move(&board);
+            assert!(game::is_legal(&board, position), "{}", difficulty.name());
+        }
+    }
+
+    #[test]
+    fn elo_differences_are_symmetric_and_clamped() {
+        assert_eq!(elo_difference(0.5), 0.0);
+        for ratio in [0.6, 0.75, 0.9, 1.0] {
+            let difference = elo_difference(ratio);
+            assert!(difference > 0.0);
+            assert!((difference + elo_difference(1.0 - ratio)).abs() < 1e-9);
+        }
+        assert_eq!(elo_difference(1.0), elo_difference(0.99));
+        assert!((elo_difference(1.0) - 800.0).abs() < 5.0);
+    }
+}
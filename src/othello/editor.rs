@@ -0,0 +1,158 @@
+//! Setting up arbitrary positions to play or analyse from.
+
+use super::moai::BitBoard;
+
+/// What clicking a square does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tool {
+    /// Empty, then black, then white, then empty again.
+    Toggle,
+    Black,
+    White,
+    Remove,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 4] = [Tool::Toggle, Tool::Black, Tool::White, Tool::Remove];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tool::Toggle => "Toggle",
+            Tool::Black => "Black",
+            Tool::White => "White",
+            Tool::Remove => "Remove",
+        }
+    }
+}
+
+/// A position being edited, which need not be reachable in a game.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Editor {
+    pub black: u64,
+    pub white: u64,
+    /// Side to move, 0 for black.
+    pub player: i32,
+    pub tool: Tool,
+}
+
+/// The four centre squares, occupied from the start of every game.
+const CENTRE: u64 = 0x0000_0018_1800_0000;
+
+impl Editor {
+    pub fn new(board: &BitBoard) -> Self {
+        let (black, white) = board.bits();
+        Self {
+            black,
+            white,
+            player: board.player(),
+            tool: Tool::Toggle,
+        }
+    }
+
+    /// Uses the tool on the square `bit`.
+    pub fn apply(&mut self, bit: u64) {
+        let (black, white) = (self.black & bit != 0, self.white & bit != 0);
+        let (black, white) = match self.tool {
+            Tool::Toggle => (!black && !white, black),
+            Tool::Black => (true, false),
+            Tool::White => (false, true),
+            Tool::Remove => (false, false),
+        };
+        self.black = if black {
+            self.black | bit
+        } else {
+            self.black & !bit
+        };
+        self.white = if white {
+            self.white | bit
+        } else {
+            self.white & !bit
+        };
+    }
+
+    pub fn clear(&mut self) {
+        self.black = 0;
+        self.white = 0;
+    }
+
+    /// Sets up the start of a game.
+    pub fn reset(&mut self) {
+        let (black, white) = BitBoard::initial().bits();
+        self.black = black;
+        self.white = white;
+        self.player = 0;
+    }
+
+    pub fn board(&self) -> BitBoard {
+        BitBoard::from_bits(self.black, self.white, self.player)
+    }
+
+    /// The position, if a game can go on from it: the centre squares are
+    /// taken as in any game, and the side to move has a legal move.
+    pub fn validate(&self) -> Result<BitBoard, String> {
+        let board = self.board();
+        if (self.black | self.white) & CENTRE != CENTRE {
+            return Err("The four centre squares must be taken.".to_owned());
+        }
+        if board.is_game_ended() {
+            return Err("Neither side has a legal move.".to_owned());
+        }
+        if board.legal_bits() == 0 {
+            let side = if self.player == 0 { "Black" } else { "White" };
+            return Err(format!("{} has no legal move to play.", side));
+        }
+
+        Ok(board)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::othello::game::parse_square;
+
+    fn place(editor: &mut Editor, tool: Tool, squares: &[&str]) {
+        editor.tool = tool;
+        for square in squares {
+            editor.apply(parse_square(square).unwrap());
+        }
+    }
+
+    #[test]
+    fn tools_edit_and_positions_are_validated() {
+        let mut editor = Editor::new(&BitBoard::initial());
+        assert_eq!(editor.validate(), Ok(BitBoard::initial()));
+
+        let a1 = parse_square("a1").unwrap();
+        for expected in [(a1, 0), (0, a1), (0, 0)] {
+            editor.apply(a1);
+            assert_eq!((editor.black & a1, editor.white & a1), expected);
+        }
+        editor.tool = Tool::White;
+        editor.apply(a1);
+        editor.tool = Tool::Remove;
+        editor.apply(a1);
+        assert_eq!(editor.board(), BitBoard::initial());
+
+        editor.apply(parse_square("d4").unwrap());
+        assert!(editor.validate().is_err());
+        editor.clear();
+        assert!(editor.validate().is_err());
+
+        // White alone on the board: the game is over.
+        place(&mut editor, Tool::White, &["d4", "e4", "d5", "e5"]);
+        assert!(editor.validate().is_err());
+        // Only black can play, at c1.
+        place(&mut editor, Tool::Black, &["d4", "e4", "d5", "e5", "a1"]);
+        place(&mut editor, Tool::White, &["b1"]);
+        editor.player = 1;
+        assert!(editor.validate().is_err());
+        editor.player = 0;
+        assert_eq!(
+            editor.validate().unwrap().moves(),
+            vec![parse_square("c1").unwrap()]
+        );
+        editor.reset();
+        assert_eq!(editor.validate(), Ok(BitBoard::initial()));
+    }
+}
@@ -0,0 +1,378 @@
+//! A common interface to the AIs, so that the GUI, the command line tools
+//! and the tests can swap them freely.
+
+pub mod baseline;
+pub mod external;
+pub mod mcts;
+pub mod pattern;
+
+use super::moai::{BitBoard, FinalMovePolicy, SearchParams, SelectionPolicy};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Simulations per unit of NBoard's `set depth`. MCTS has no search depth,
+/// so depths are turned into a budget and back.
+pub const ITERATIONS_PER_DEPTH: i32 = 1000;
+
+/// `SearchInfo::score` estimates the expected result from -1 to 1 rather
+/// than a disk count. NBoard evaluations are in disks, so scores are
+/// stretched to that scale and back.
+pub const DISKS_PER_SCORE: f64 = 64.0;
+
+/// Search budget for `Engine::think`. Limits that are `None` are not
+/// enforced, so with none at all a search runs until it is stopped.
+/// Engines that need no budget ignore them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Milliseconds.
+    pub time: Option<u128>,
+    /// Simulations, for engines that count them.
+    pub iterations: Option<i32>,
+}
+
+impl Limits {
+    pub fn time(time: u128) -> Self {
+        Self {
+            time: Some(time),
+            iterations: None,
+        }
+    }
+
+    pub fn iterations(iterations: i32) -> Self {
+        Self {
+            time: None,
+            iterations: Some(iterations),
+        }
+    }
+}
+
+/// Statistics of one root move.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MoveStat {
+    pub position: u64,
+    pub visits: usize,
+    /// Expected result for the side to move, from -1 (loss) to 1 (win).
+    pub score: f64,
+}
+
+/// Progress of a search, passed to the info callback of `Engine::think`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchInfo {
+    pub best_move: u64,
+    /// Expected result for the side to move, from -1 (loss) to 1 (win).
+    pub score: f64,
+    pub iterations: i32,
+    /// Milliseconds since the search started.
+    pub elapsed: u128,
+    /// Principal variation, starting with `best_move`.
+    pub pv: Vec<u64>,
+    /// Root moves the engine has an opinion about.
+    pub moves: Vec<MoveStat>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineError {
+    /// `think` was called before `set_position`.
+    NoPosition,
+    /// The engine did not answer in time.
+    Timeout,
+    /// The engine stopped working (e.g. its process exited).
+    Crashed(String),
+    /// The engine answered something that does not make sense.
+    Protocol(String),
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::NoPosition => write!(f, "no position set"),
+            EngineError::Timeout => write!(f, "engine timed out"),
+            EngineError::Crashed(reason) => write!(f, "engine crashed: {}", reason),
+            EngineError::Protocol(reason) => write!(f, "protocol error: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+/// Makes a running `Engine::think` return early with the best move found so
+/// far. It can be cloned and used from another thread.
+#[derive(Clone, Debug, Default)]
+pub struct StopHandle(Arc<AtomicBool>);
+
+impl StopHandle {
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Called by engines when given a position, so that a stop requested
+    /// after that but before `think` still ends the search at once.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+pub trait Engine: Send {
+    fn name(&self) -> String;
+
+    /// Forgets everything learned about the previous game.
+    fn new_game(&mut self) {}
+
+    fn set_position(&mut self, board: BitBoard);
+
+    /// Value of a draw for the side to move, on the scale of
+    /// `SearchInfo::score`. Engines that do not search ignore it.
+    fn set_draw_score(&mut self, _draw_score: f64) {}
+
+    /// Searches the position given to `set_position` and returns the move to
+    /// play, or 0 to pass. `info` is called with the progress from time to
+    /// time and always once at the end.
+    fn think(
+        &mut self,
+        limits: Limits,
+        info: &mut dyn FnMut(&SearchInfo),
+    ) -> Result<u64, EngineError>;
+
+    fn stop_handle(&self) -> StopHandle;
+}
+
+/// The built-in engines and their settings.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "persistence", derive(serde::Deserialize, serde::Serialize))]
+pub enum EngineKind {
+    Mcts(SearchParams),
+    Random,
+    Greedy,
+    Mobility,
+    Corner,
+    /// A program speaking the NBoard protocol, with its arguments.
+    External(String),
+    /// Alpha-beta on pattern weights, with the path of the weights file.
+    Pattern(String),
+    /// MCTS guided by a network, with the path of the network file.
+    Network(String),
+}
+
+impl EngineKind {
+    /// Every built-in engine with its default settings.
+    pub fn builtin() -> Vec<EngineKind> {
+        vec![
+            EngineKind::Mcts(SearchParams::default()),
+            EngineKind::Random,
+            EngineKind::Greedy,
+            EngineKind::Mobility,
+            EngineKind::Corner,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EngineKind::Mcts(_) => "mcts",
+            EngineKind::Random => "random",
+            EngineKind::Greedy => "greedy",
+            EngineKind::Mobility => "mobility",
+            EngineKind::Corner => "corner",
+            EngineKind::External(_) => "external",
+            EngineKind::Pattern(_) => "pattern",
+            EngineKind::Network(_) => "network",
+        }
+    }
+
+    /// Whether the engine searches and reports every legal move in
+    /// `SearchInfo::moves`, which the analysis draws.
+    pub fn reports_moves(&self) -> bool {
+        matches!(
+            self,
+            EngineKind::Mcts(_) | EngineKind::Pattern(_) | EngineKind::Network(_)
+        )
+    }
+
+    pub fn create(&self) -> Box<dyn Engine> {
+        match self {
+            EngineKind::Mcts(params) => Box::new(mcts::MctsEngine::new(*params)),
+            EngineKind::Random => Box::new(baseline::OnePlyEngine::random()),
+            EngineKind::Greedy => Box::new(baseline::OnePlyEngine::greedy()),
+            EngineKind::Mobility => Box::new(baseline::OnePlyEngine::mobility()),
+            EngineKind::Corner => Box::new(baseline::OnePlyEngine::corner()),
+            EngineKind::External(command) => {
+                Box::new(external::ExternalEngine::from_command_line(command))
+            }
+            EngineKind::Pattern(path) => Box::new(pattern::PatternEngine::from_file(path)),
+            EngineKind::Network(path) => Box::new(mcts::MctsEngine::from_network_file(
+                SearchParams::default(),
+                path,
+            )),
+        }
+    }
+
+    /// Parses specs such as `greedy`, `mcts:cp=1.5,selection=puct`,
+    /// `external:/path/to/engine --nboard`, `pattern:weights.bin` or
+    /// `network:network.bin`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (name, options) = match spec.find(':') {
+            Some(i) => (&spec[..i], &spec[i + 1..]),
+            None => (spec, ""),
+        };
+        let kind = match name {
+            "mcts" => EngineKind::Mcts(parse_search_params(options)?),
+            "random" => EngineKind::Random,
+            "greedy" => EngineKind::Greedy,
+            "mobility" => EngineKind::Mobility,
+            "corner" => EngineKind::Corner,
+            "external" if options.trim().is_empty() => {
+                return Err("external needs a command".to_owned())
+            }
+            "external" => EngineKind::External(options.trim().to_owned()),
+            "pattern" if options.trim().is_empty() => {
+                return Err("pattern needs a weights file".to_owned())
+            }
+            "pattern" => EngineKind::Pattern(options.trim().to_owned()),
+            "network" if options.trim().is_empty() => {
+                return Err("network needs a network file".to_owned())
+            }
+            "network" => EngineKind::Network(options.trim().to_owned()),
+            _ => return Err(format!("unknown engine: {}", name)),
+        };
+        let takes_options = matches!(
+            kind,
+            EngineKind::Mcts(_)
+                | EngineKind::External(_)
+                | EngineKind::Pattern(_)
+                | EngineKind::Network(_)
+        );
+        if !options.is_empty() && !takes_options {
+            return Err(format!("{} takes no options", name));
+        }
+
+        Ok(kind)
+    }
+
+    /// The inverse of `parse`.
+    pub fn spec(&self) -> String {
+        match self {
+            EngineKind::Mcts(params) => format!(
+                "mcts:cp={},playout={},selection={},final={}",
+                params.cp,
+                params.playout,
+                selection_key(params.selection),
+                final_move_key(params.final_move)
+            ),
+            EngineKind::External(command) => format!("external:{}", command),
+            EngineKind::Pattern(path) => format!("pattern:{}", path),
+            EngineKind::Network(path) => format!("network:{}", path),
+            _ => self.name().to_owned(),
+        }
+    }
+}
+
+fn selection_key(selection: SelectionPolicy) -> &'static str {
+    match selection {
+        SelectionPolicy::Ucb1 => "ucb1",
+        SelectionPolicy::Ucb1Tuned => "ucb1-tuned",
+        SelectionPolicy::Puct => "puct",
+    }
+}
+
+fn final_move_key(final_move: FinalMovePolicy) -> &'static str {
+    match final_move {
+        FinalMovePolicy::MostVisits => "visits",
+        FinalMovePolicy::BestMean => "mean",
+        FinalMovePolicy::RobustMax => "robust",
+    }
+}
+
+fn parse_search_params(options: &str) -> Result<SearchParams, String> {
+    let mut params = SearchParams::default();
+    for option in options.split(',').filter(|option| !option.is_empty()) {
+        let mut parts = option.splitn(2, '=');
+        let key = parts.next().unwrap();
+        let value = parts
+            .next()
+            .ok_or_else(|| format!("missing value for {}", key))?;
+        match key {
+            "cp" => {
+                params.cp = value
+                    .parse()
+                    .ok()
+                    .filter(|cp: &f64| cp.is_finite() && *cp >= 0.0)
+                    .ok_or_else(|| format!("bad cp: {}", value))?
+            }
+            "playout" => {
+                params.playout = value
+                    .parse()
+                    .ok()
+                    .filter(|playout| *playout >= 1)
+                    .ok_or_else(|| format!("bad playout: {}", value))?
+            }
+            "selection" => {
+                params.selection = SelectionPolicy::ALL
+                    .iter()
+                    .copied()
+                    .find(|selection| selection_key(*selection) == value)
+                    .ok_or_else(|| format!("bad selection: {}", value))?
+            }
+            "final" => {
+                params.final_move = FinalMovePolicy::ALL
+                    .iter()
+                    .copied()
+                    .find(|final_move| final_move_key(*final_move) == value)
+                    .ok_or_else(|| format!("bad final: {}", value))?
+            }
+            _ => return Err(format!("unknown option: {}", key)),
+        }
+    }
+
+    Ok(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn specs_round_trip() {
+        let mut kinds = EngineKind::builtin();
+        kinds.push(EngineKind::parse("mcts:cp=0.7,playout=3,selection=ucb1,final=robust").unwrap());
+        kinds.push(EngineKind::External("edax -n 1".to_owned()));
+        kinds.push(EngineKind::Pattern("weights.bin".to_owned()));
+        kinds.push(EngineKind::Network("network.bin".to_owned()));
+        for kind in kinds {
+            assert_eq!(
+                EngineKind::parse(&kind.spec()),
+                Ok(kind.clone()),
+                "{}",
+                kind.spec()
+            );
+        }
+        assert_eq!(
+            EngineKind::parse("mcts"),
+            Ok(EngineKind::Mcts(SearchParams::default()))
+        );
+    }
+
+    #[test]
+    fn bad_specs_are_errors() {
+        for spec in [
+            "",
+            "alphabeta",
+            "greedy:fast",
+            "external:",
+            "pattern: ",
+            "mcts:cp",
+            "mcts:cp=-1",
+            "mcts:cp=NaN",
+            "mcts:cp=inf",
+            "mcts:playout=0",
+            "mcts:selection=best",
+            "mcts:final=last",
+            "mcts:depth=3",
+        ] {
+            assert!(EngineKind::parse(spec).is_err(), "{:?}", spec);
+        }
+    }
+}
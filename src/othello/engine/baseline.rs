@@ -0,0 +1,295 @@
+//! Simple engines that pick a move with a one-ply rule. They are useful as
+//! weak opponents and as reference points when measuring stronger engines.
+
+use super::{Engine, EngineError, Limits, SearchInfo, StopHandle};
+use crate::othello::moai::BitBoard;
+use rand::Rng;
+
+const CORNERS: u64 = 0x8100_0000_0000_0081;
+
+/// The squares diagonally next to each corner, with the corner itself.
+const X_SQUARES: [(u64, u64); 4] = [
+    (0x8000_0000_0000_0000, 0x0040_0000_0000_0000),
+    (0x0100_0000_0000_0000, 0x0002_0000_0000_0000),
+    (0x0000_0000_0000_0080, 0x0000_0000_0000_4000),
+    (0x0000_0000_0000_0001, 0x0000_0000_0000_0200),
+];
+
+/// Picks the legal move with the highest `key`, breaking ties at random,
+/// and reports it to `info`.
+fn best_by<K>(
+    position: Option<BitBoard>,
+    info: &mut dyn FnMut(&SearchInfo),
+    mut key: K,
+) -> Result<u64, EngineError>
+where
+    K: FnMut(&BitBoard, u64) -> i32,
+{
+    let board = position.ok_or(EngineError::NoPosition)?;
+    let mut rng = rand::thread_rng();
+    let mut best = Vec::new();
+    let mut best_key = i32::MIN;
+    for position in board.moves() {
+        let k = key(&board, position);
+        if k > best_key {
+            best_key = k;
+            best.clear();
+        }
+        if k == best_key {
+            best.push(position);
+        }
+    }
+
+    let best_move = if best.is_empty() {
+        0
+    } else {
+        best[rng.gen_range(0..best.len())]
+    };
+    info(&SearchInfo {
+        best_move,
+        pv: vec![best_move],
+        ..Default::default()
+    });
+    Ok(best_move)
+}
+
+/// An engine that plays the legal move maximising a static rule.
+pub struct OnePlyEngine {
+    name: &'static str,
+    key: fn(&BitBoard, u64) -> i32,
+    position: Option<BitBoard>,
+    stop: StopHandle,
+}
+
+impl OnePlyEngine {
+    fn new(name: &'static str, key: fn(&BitBoard, u64) -> i32) -> Self {
+        Self {
+            name,
+            key,
+            position: None,
+            stop: StopHandle::default(),
+        }
+    }
+
+    /// Plays a uniformly random legal move.
+    pub fn random() -> Self {
+        Self::new("random", |_, _| 0)
+    }
+
+    /// Plays the move flipping the most disks.
+    pub fn greedy() -> Self {
+        Self::new("greedy", |board, position| {
+            board.flips(position).count_ones() as i32
+        })
+    }
+
+    /// Plays the move leaving the opponent the fewest replies, preferring
+    /// moves that keep many moves for itself.
+    pub fn mobility() -> Self {
+        Self::new("mobility", mobility)
+    }
+
+    /// Takes corners, avoids the squares that give them away and otherwise
+    /// plays greedily.
+    pub fn corner() -> Self {
+        Self::new("corner", corner)
+    }
+}
+
+impl Engine for OnePlyEngine {
+    fn name(&self) -> String {
+        self.name.to_owned()
+    }
+
+    fn set_position(&mut self, board: BitBoard) {
+        self.position = Some(board);
+    }
+
+    fn think(
+        &mut self,
+        _limits: Limits,
+        info: &mut dyn FnMut(&SearchInfo),
+    ) -> Result<u64, EngineError> {
+        best_by(self.position, info, self.key)
+    }
+
+    fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+}
+
+fn mobility(board: &BitBoard, position: u64) -> i32 {
+    let next = board.play(position);
+    let theirs = next.legal_bits().count_ones() as i32;
+    let ours = next.play(0).legal_bits().count_ones() as i32;
+    ours - 4 * theirs
+}
+
+fn corner(board: &BitBoard, position: u64) -> i32 {
+    let flips = board.flips(position).count_ones() as i32;
+    if position & CORNERS != 0 {
+        return 1000 + flips;
+    }
+    for (corner, x_square) in X_SQUARES.iter() {
+        if position & x_square != 0 && !board.is_occupied(*corner) {
+            return -1000 + flips;
+        }
+    }
+
+    flips
+}
+
+/// Plays a random legal move with probability `randomness` and otherwise
+/// lets `inner` decide. The choice is made once per position, so a search
+/// run in slices does not roll again on every slice.
+pub struct Randomized {
+    inner: Box<dyn Engine>,
+    randomness: f64,
+    position: Option<BitBoard>,
+    random: bool,
+}
+
+impl Randomized {
+    /// Fails unless `randomness` is a probability.
+    pub fn new(inner: Box<dyn Engine>, randomness: f64) -> Result<Self, String> {
+        if !(0.0..=1.0).contains(&randomness) {
+            return Err(format!(
+                "randomness must be between 0 and 1: {}",
+                randomness
+            ));
+        }
+
+        Ok(Self {
+            inner,
+            randomness,
+            position: None,
+            random: false,
+        })
+    }
+}
+
+impl Engine for Randomized {
+    fn name(&self) -> String {
+        format!(
+            "{} ({:.0}% random)",
+            self.inner.name(),
+            self.randomness * 100.0
+        )
+    }
+
+    fn new_game(&mut self) {
+        self.inner.new_game();
+    }
+
+    fn set_position(&mut self, board: BitBoard) {
+        self.position = Some(board);
+        self.random = rand::thread_rng().gen_bool(self.randomness);
+        self.inner.set_position(board);
+    }
+
+    fn set_draw_score(&mut self, draw_score: f64) {
+        self.inner.set_draw_score(draw_score);
+    }
+
+    fn think(
+        &mut self,
+        limits: Limits,
+        info: &mut dyn FnMut(&SearchInfo),
+    ) -> Result<u64, EngineError> {
+        let board = self.position.ok_or(EngineError::NoPosition)?;
+        if self.random && !board.moves().is_empty() {
+            return best_by(self.position, info, |_, _| 0);
+        }
+
+        self.inner.think(limits, info)
+    }
+
+    fn stop_handle(&self) -> StopHandle {
+        self.inner.stop_handle()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::seq::SliceRandom;
+
+    /// Positions of random games, with a move to play.
+    fn positions() -> Vec<BitBoard> {
+        let mut rng = rand::thread_rng();
+        let mut positions = Vec::new();
+        for _ in 0..5 {
+            let mut board = BitBoard::initial();
+            while !board.is_game_ended() {
+                let moves = board.moves();
+                if !moves.is_empty() {
+                    positions.push(board);
+                }
+                board = board.play(moves.choose(&mut rng).copied().unwrap_or(0));
+            }
+        }
+
+        positions
+    }
+
+    fn play(engine: &mut dyn Engine, board: BitBoard) -> u64 {
+        engine.set_position(board);
+        engine.think(Limits::default(), &mut |_| {}).unwrap()
+    }
+
+    /// Black to move: a1 takes a corner flipping one disk, f5 flips four.
+    fn corner_or_capture() -> BitBoard {
+        let mut rows = vec!["........".to_owned(); 8];
+        rows[0] = ".10.....".to_owned();
+        rows[4] = "01111...".to_owned();
+        BitBoard::from_strings(rows, 0)
+    }
+
+    #[test]
+    fn one_ply_engines_follow_their_rule() {
+        let engines = [
+            OnePlyEngine::greedy(),
+            OnePlyEngine::mobility(),
+            OnePlyEngine::corner(),
+        ];
+        for mut engine in engines {
+            let key = engine.key;
+            for board in positions() {
+                let best = board.moves().iter().map(|&m| key(&board, m)).max();
+                let position = play(&mut engine, board);
+                assert_eq!(Some(key(&board, position)), best, "{}", engine.name());
+            }
+        }
+
+        let board = corner_or_capture();
+        assert_eq!(board.moves().len(), 2);
+        let f5 = 1 << (63 - (4 * 8 + 5));
+        assert_eq!(play(&mut OnePlyEngine::greedy(), board), f5);
+        assert_eq!(play(&mut OnePlyEngine::corner(), board), 1 << 63);
+        // White could only reply to f5, with d1.
+        assert_eq!(play(&mut OnePlyEngine::mobility(), board), 1 << 63);
+    }
+
+    #[test]
+    fn randomized_plays_the_inner_move_or_a_random_one() {
+        let board = corner_or_capture();
+        let greedy = play(&mut OnePlyEngine::greedy(), board);
+        let mut never = Randomized::new(Box::new(OnePlyEngine::greedy()), 0.0).unwrap();
+        assert!((0..20).all(|_| play(&mut never, board) == greedy));
+        let mut always = Randomized::new(Box::new(OnePlyEngine::greedy()), 1.0).unwrap();
+        assert!((0..100).any(|_| play(&mut always, board) != greedy));
+    }
+
+    #[test]
+    fn randomness_is_a_probability() {
+        for randomness in [-0.1, 1.5, f64::NAN] {
+            let inner = Box::new(OnePlyEngine::greedy());
+            assert!(
+                Randomized::new(inner, randomness).is_err(),
+                "{}",
+                randomness
+            );
+        }
+        assert!(Randomized::new(Box::new(OnePlyEngine::greedy()), 1.0).is_ok());
+    }
+}
@@ -0,0 +1,313 @@
+use super::{
+    Engine, EngineError, Limits, SearchInfo, StopHandle, DISKS_PER_SCORE, ITERATIONS_PER_DEPTH,
+};
+use crate::othello::game::{parse_square, Game};
+use crate::othello::ggf::GgfGame;
+use crate::othello::moai::BitBoard;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a program may take to start or to answer a `ping`.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for a move when the limits give no time.
+const MOVE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Interval at which the stop handle is checked while waiting.
+const POLL: Duration = Duration::from_millis(50);
+
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl Process {
+    fn spawn(command: &[String]) -> Result<Self, EngineError> {
+        let program = command
+            .first()
+            .ok_or_else(|| EngineError::Crashed("no command".to_owned()))?;
+        let mut child = Command::new(program)
+            .args(&command[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| EngineError::Crashed(format!("cannot start {}: {}", program, err)))?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            child,
+            stdin,
+            lines,
+        })
+    }
+
+    fn send(&mut self, message: &str) -> Result<(), EngineError> {
+        writeln!(self.stdin, "{}", message)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|err| EngineError::Crashed(err.to_string()))
+    }
+
+    /// The next line, waiting at most `timeout`.
+    fn receive(&mut self, timeout: Duration) -> Result<String, EngineError> {
+        match self.lines.recv_timeout(timeout) {
+            Ok(line) => Ok(line),
+            Err(RecvTimeoutError::Timeout) => Err(EngineError::Timeout),
+            Err(RecvTimeoutError::Disconnected) => {
+                let status = self.child.wait().map_or_else(
+                    |err| err.to_string(),
+                    |status| format!("exited with {}", status),
+                );
+                Err(EngineError::Crashed(status))
+            }
+        }
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A program speaking the NBoard protocol, run as a subprocess. It is
+/// started on first use and restarted after it crashes or times out.
+///
+/// Time limits are passed on as the game clock, which is the only way the
+/// protocol has to tell the time; the program is given the time of all the
+/// moves left to play. A program that answers late is stopped with
+/// `EngineError::Timeout`.
+pub struct ExternalEngine {
+    command: Vec<String>,
+    process: Option<Process>,
+    /// Sent by the program with `set myname`.
+    name: Option<String>,
+    position: Option<BitBoard>,
+    ping: u32,
+    stop: StopHandle,
+}
+
+impl ExternalEngine {
+    /// `command` is the program followed by its arguments.
+    pub fn new(command: Vec<String>) -> Self {
+        Self {
+            command,
+            process: None,
+            name: None,
+            position: None,
+            ping: 0,
+            stop: StopHandle::default(),
+        }
+    }
+
+    /// Splits a command line on whitespace; quoting is not supported.
+    pub fn from_command_line(command_line: &str) -> Self {
+        Self::new(command_line.split_whitespace().map(str::to_owned).collect())
+    }
+
+    /// The running process, started if needed, after all its pending
+    /// output has been read.
+    fn process(&mut self) -> Result<&mut Process, EngineError> {
+        if self.process.is_none() {
+            let mut process = Process::spawn(&self.command)?;
+            process.send("nboard 2")?;
+            self.process = Some(process);
+        }
+        self.ping += 1;
+        let ping = self.ping;
+        let process = self.process.as_mut().unwrap();
+        process.send(&format!("ping {}", ping))?;
+        let deadline = Instant::now() + STARTUP_TIMEOUT;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let line = process.receive(timeout)?;
+            if let Some(name) = line.strip_prefix("set myname ") {
+                self.name = Some(name.trim().to_owned());
+            } else if line.trim() == format!("pong {}", ping) {
+                return Ok(process);
+            }
+        }
+    }
+
+    fn search(
+        &mut self,
+        board: BitBoard,
+        limits: Limits,
+        info: &mut dyn FnMut(&SearchInfo),
+    ) -> Result<u64, EngineError> {
+        let stop = self.stop.clone();
+        let process = self.process()?;
+        let depth = limits.iterations.map_or(60, |iterations| {
+            (iterations / ITERATIONS_PER_DEPTH).clamp(1, 60)
+        });
+        process.send(&format!("set depth {}", depth))?;
+        let mut ggf = GgfGame::new(Game::new(board), "", "").to_string();
+        if let Some(time) = limits.time {
+            let (black, white) = board.count();
+            let moves = (64 - black - white).div_ceil(2);
+            let clock = (time * moves as u128).div_ceil(1000);
+            let clock = format!("TI[{}:{:02}]", clock / 60, clock % 60);
+            ggf = ggf.replacen("TY[", &format!("{}TY[", clock), 1);
+        }
+        process.send(&format!("set game {}", ggf))?;
+        process.send("go")?;
+
+        let start = Instant::now();
+        let timeout = limits.time.map_or(MOVE_TIMEOUT, |time| {
+            Duration::from_millis(time as u64 * 2 + 1000)
+        });
+        let waits_for_stop = limits.time.is_none() && limits.iterations.is_none();
+        let mut search_info = SearchInfo::default();
+        loop {
+            if stop.is_stopped() && search_info.best_move != 0 {
+                return Ok(search_info.best_move);
+            }
+            if !waits_for_stop && start.elapsed() > timeout {
+                return Err(EngineError::Timeout);
+            }
+            let line = match process.receive(POLL) {
+                Ok(line) => line,
+                Err(EngineError::Timeout) => continue,
+                Err(err) => return Err(err),
+            };
+            search_info.elapsed = start.elapsed().as_millis();
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("===") => {
+                    let answer = words.next().unwrap_or("");
+                    let mut parts = answer.split('/');
+                    let position = parts
+                        .next()
+                        .and_then(parse_square)
+                        .ok_or_else(|| EngineError::Protocol(line.clone()))?;
+                    if let Some(eval) = parts.next().and_then(|eval| eval.parse::<f64>().ok()) {
+                        search_info.score = (eval / DISKS_PER_SCORE).clamp(-1.0, 1.0);
+                    }
+                    search_info.best_move = position;
+                    if search_info.pv.first() != Some(&position) {
+                        search_info.pv = vec![position];
+                    }
+                    info(&search_info);
+                    return Ok(position);
+                }
+                Some("search") => {
+                    let pv = parse_pv(words.next().unwrap_or(""));
+                    let eval = words.next().and_then(|eval| eval.parse::<f64>().ok());
+                    if let (Some(&best_move), Some(eval)) = (pv.first(), eval) {
+                        search_info.best_move = best_move;
+                        search_info.score = (eval / DISKS_PER_SCORE).clamp(-1.0, 1.0);
+                        search_info.pv = pv;
+                        info(&search_info);
+                    }
+                }
+                Some("nodestats") => {
+                    if let Some(nodes) = words.next().and_then(|n| n.parse::<u64>().ok()) {
+                        search_info.iterations = nodes.min(i32::MAX as u64) as i32;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Moves written one after the other, such as `F5D6C3`.
+fn parse_pv(text: &str) -> Vec<u64> {
+    (0..text.len() / 2)
+        .map_while(|i| text.get(i * 2..i * 2 + 2).and_then(parse_square))
+        .collect()
+}
+
+impl Engine for ExternalEngine {
+    fn name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => format!("external ({})", self.command.join(" ")),
+        }
+    }
+
+    fn set_position(&mut self, board: BitBoard) {
+        self.position = Some(board);
+        self.stop.reset();
+    }
+
+    fn think(
+        &mut self,
+        limits: Limits,
+        info: &mut dyn FnMut(&SearchInfo),
+    ) -> Result<u64, EngineError> {
+        let board = self.position.ok_or(EngineError::NoPosition)?;
+        let result = self.search(board, limits, info);
+        if let Err(EngineError::Timeout) | Err(EngineError::Crashed(_)) = result {
+            // Restarted on the next call.
+            self.process = None;
+        }
+
+        result
+    }
+
+    fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    /// A fake engine written in shell, answering `go` with `reply`.
+    fn script(reply: &str) -> ExternalEngine {
+        let script = format!(
+            "echo 'set myname fake'; while read c a; do case $c in \
+             ping) echo pong $a;; go) {};; esac; done",
+            reply
+        );
+        ExternalEngine::new(vec!["sh".to_owned(), "-c".to_owned(), script])
+    }
+
+    #[test]
+    fn plays_the_programs_move() {
+        let mut engine = script("echo search F5D6 2.5 0 4; echo === F5/2.5/0.1");
+        engine.set_position(BitBoard::initial());
+        let mut last = SearchInfo::default();
+        let position = engine
+            .think(Limits::time(1000), &mut |info| last = info.clone())
+            .unwrap();
+        assert_eq!(Some(position), parse_square("f5"));
+        assert_eq!(last.pv.len(), 2);
+        assert!(last.score > 0.0);
+        assert_eq!(engine.name(), "fake");
+    }
+
+    #[test]
+    fn reports_crashes_and_timeouts() {
+        let mut engine = script("exit 3");
+        engine.set_position(BitBoard::initial());
+        let result = engine.think(Limits::time(1000), &mut |_| {});
+        assert!(matches!(result, Err(EngineError::Crashed(_))));
+
+        let mut engine = script("true");
+        engine.set_position(BitBoard::initial());
+        let result = engine.think(Limits::time(10), &mut |_| {});
+        assert_eq!(result, Err(EngineError::Timeout));
+
+        let mut engine = ExternalEngine::from_command_line("/nonexistent/engine");
+        engine.set_position(BitBoard::initial());
+        let result = engine.think(Limits::time(10), &mut |_| {});
+        assert!(matches!(result, Err(EngineError::Crashed(_))));
+    }
+}
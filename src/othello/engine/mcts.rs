@@ -0,0 +1,216 @@
+use super::{Engine, EngineError, Limits, MoveStat, SearchInfo, StopHandle};
+use crate::othello::moai::{BitBoard, SearchParams, MCTS};
+use crate::othello::network::Network;
+use std::sync::Arc;
+use wasm_timer::Instant;
+
+/// Milliseconds between two progress reports.
+const INFO_INTERVAL: u128 = 100;
+
+/// `MCTS` behind the `Engine` interface. The tree is kept between calls to
+/// `think`, so searching a position that follows from the previous one
+/// reuses the earlier work.
+pub struct MctsEngine {
+    mcts: MCTS,
+    params: SearchParams,
+    draw_score: f64,
+    position: Option<BitBoard>,
+    stop: StopHandle,
+    /// The network guiding the search, if any, or why it could not be
+    /// loaded.
+    network: Result<Option<Arc<Network>>, String>,
+}
+
+impl MctsEngine {
+    pub fn new(params: SearchParams) -> Self {
+        Self {
+            mcts: MCTS::from_params(params),
+            params,
+            draw_score: 0.0,
+            position: None,
+            stop: StopHandle::default(),
+            network: Ok(None),
+        }
+    }
+
+    /// A search guided by `network`, see `MCTS::with_network`.
+    pub fn with_network(params: SearchParams, network: Arc<Network>) -> Self {
+        Self {
+            mcts: MCTS::from_params(params).with_network(network.clone()),
+            network: Ok(Some(network)),
+            ..Self::new(params)
+        }
+    }
+
+    /// Loads the network from `path`. Failing to do so is reported by
+    /// `think`.
+    pub fn from_network_file(params: SearchParams, path: &str) -> Self {
+        match Network::load(path) {
+            Ok(network) => Self::with_network(params, Arc::new(network)),
+            Err(err) => Self {
+                network: Err(format!("cannot load {}: {}", path, err)),
+                ..Self::new(params)
+            },
+        }
+    }
+
+    fn new_search(&self) -> MCTS {
+        let mcts = MCTS::from_params(self.params);
+        match &self.network {
+            Ok(Some(network)) => mcts.with_network(network.clone()),
+            _ => mcts,
+        }
+    }
+
+    fn info(&self, iterations: i32, elapsed: u128) -> SearchInfo {
+        let moves: Vec<MoveStat> = self
+            .mcts
+            .root_children()
+            .into_iter()
+            .map(|(position, visits, score)| MoveStat {
+                position,
+                visits,
+                score,
+            })
+            .collect();
+        let best_move = self.mcts.best_move();
+        let score = moves
+            .iter()
+            .find(|stat| stat.position == best_move)
+            .map_or(0.0, |stat| stat.score);
+
+        SearchInfo {
+            best_move,
+            score,
+            iterations,
+            elapsed,
+            pv: self.mcts.principal_variation(10),
+            moves,
+        }
+    }
+}
+
+impl Engine for MctsEngine {
+    fn name(&self) -> String {
+        if let Ok(Some(network)) = &self.network {
+            return format!("mcts (network {}, cp={})", network.hidden(), self.params.cp);
+        }
+        format!(
+            "mcts ({} cp={})",
+            self.params.selection.name(),
+            self.params.cp
+        )
+    }
+
+    fn new_game(&mut self) {
+        self.mcts = self.new_search();
+        self.mcts.set_draw_score(self.draw_score);
+    }
+
+    fn set_position(&mut self, board: BitBoard) {
+        self.position = Some(board);
+        self.stop.reset();
+    }
+
+    fn set_draw_score(&mut self, draw_score: f64) {
+        self.draw_score = draw_score;
+        self.mcts.set_draw_score(draw_score);
+    }
+
+    fn think(
+        &mut self,
+        limits: Limits,
+        info: &mut dyn FnMut(&SearchInfo),
+    ) -> Result<u64, EngineError> {
+        let position = self.position.ok_or(EngineError::NoPosition)?;
+        if let Err(err) = &self.network {
+            return Err(EngineError::Crashed(err.clone()));
+        }
+        self.mcts.set_root(position);
+
+        let inst = Instant::now();
+        let mut last_info = 0;
+        let mut count = 0;
+        loop {
+            let batch = limits
+                .iterations
+                .map_or(50, |iterations| (iterations - count).clamp(1, 50));
+            self.mcts.search(batch);
+            count += batch;
+            let elapsed = inst.elapsed().as_millis();
+            if self.stop.is_stopped()
+                || limits.time.is_some_and(|time| elapsed >= time)
+                || limits
+                    .iterations
+                    .is_some_and(|iterations| count >= iterations)
+            {
+                break;
+            }
+            if elapsed - last_info >= INFO_INTERVAL {
+                info(&self.info(count, elapsed));
+                last_info = elapsed;
+            }
+        }
+
+        if !self.stop.is_stopped() {
+            count += self
+                .mcts
+                .extend_search(inst, limits.time, limits.iterations, count);
+        }
+
+        let search_info = self.info(count, inst.elapsed().as_millis());
+        info(&search_info);
+        Ok(search_info.best_move)
+    }
+
+    fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn searches_within_its_limits() {
+        let mut engine = MctsEngine::new(SearchParams::default());
+        engine.set_position(BitBoard::initial());
+        let mut last = SearchInfo::default();
+        let best_move = engine
+            .think(Limits::iterations(300), &mut |i| last = i.clone())
+            .unwrap();
+        assert_eq!(last.iterations, 300);
+        assert_eq!(last.best_move, best_move);
+        assert!(BitBoard::initial().moves().contains(&best_move));
+        assert_eq!(last.moves.len(), 4);
+
+        let start = Instant::now();
+        engine.think(Limits::time(100), &mut |_| {}).unwrap();
+        let elapsed = start.elapsed().as_millis();
+        assert!((100..1000).contains(&elapsed), "{} ms", elapsed);
+
+        let mut unset = MctsEngine::new(SearchParams::default());
+        let result = unset.think(Limits::iterations(1), &mut |_| {});
+        assert!(matches!(result, Err(EngineError::NoPosition)));
+    }
+
+    #[test]
+    fn a_stop_before_think_is_kept() {
+        let mut engine = MctsEngine::new(SearchParams::default());
+        engine.set_position(BitBoard::initial());
+        engine.stop_handle().stop();
+        let mut last = SearchInfo::default();
+        engine
+            .think(Limits::iterations(1_000_000), &mut |i| last = i.clone())
+            .unwrap();
+        assert!(last.iterations <= 50, "{} simulations", last.iterations);
+
+        // A new position clears the request.
+        engine.set_position(BitBoard::initial());
+        engine
+            .think(Limits::iterations(200), &mut |i| last = i.clone())
+            .unwrap();
+        assert_eq!(last.iterations, 200);
+    }
+}
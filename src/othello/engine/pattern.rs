@@ -0,0 +1,276 @@
+//! An alpha-beta search evaluating positions with trained pattern weights
+//! (see `othello::pattern`), and solving the endgame exactly.
+
+use super::{
+    Engine, EngineError, Limits, MoveStat, SearchInfo, StopHandle, DISKS_PER_SCORE,
+    ITERATIONS_PER_DEPTH,
+};
+use crate::othello::moai::BitBoard;
+use crate::othello::pattern::Evaluator;
+use std::sync::Arc;
+use wasm_timer::Instant;
+
+/// Positions with this many empty squares or fewer are solved.
+const SOLVE_EMPTIES: u32 = 12;
+
+/// Positions the solver may search before falling back to the evaluation.
+const SOLVE_NODES: u64 = 2_000_000;
+
+/// Nodes between two checks of the clock and the stop handle.
+const CHECK_INTERVAL: u64 = 1024;
+
+/// Depths from which moves are ordered by the replies they leave.
+const ORDERING_DEPTH: u32 = 3;
+
+struct Search<'a> {
+    evaluator: &'a Evaluator,
+    board: BitBoard,
+    stop: &'a StopHandle,
+    start: Instant,
+    time: Option<u128>,
+    nodes: u64,
+    aborted: bool,
+}
+
+impl Search<'_> {
+    /// Negamax value in disks of the position with `player` to move, or
+    /// `None` once the search is out of time.
+    fn negamax(
+        &mut self,
+        player: u64,
+        opponent: u64,
+        depth: u32,
+        mut alpha: f32,
+        beta: f32,
+    ) -> Option<f32> {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(CHECK_INTERVAL) {
+            let out_of_time = self
+                .time
+                .is_some_and(|time| self.start.elapsed().as_millis() >= time);
+            self.aborted = out_of_time || self.stop.is_stopped();
+        }
+        if self.aborted {
+            return None;
+        }
+
+        let mut moves = self.board.legal_move_bits(player, opponent);
+        if moves == 0 {
+            if self.board.legal_move_bits(opponent, player) == 0 {
+                return Some(BitBoard::from_bits(player, opponent, 0).final_score() as f32);
+            }
+            return Some(-self.negamax(opponent, player, depth, -beta, -alpha)?);
+        }
+        if depth == 0 {
+            return Some(self.evaluator.evaluate_bits(player, opponent));
+        }
+
+        let mut ordered = [0u64; 64];
+        let mut count = 0;
+        while moves != 0 {
+            let position = moves & moves.wrapping_neg();
+            moves ^= position;
+            ordered[count] = position;
+            count += 1;
+        }
+        if depth >= ORDERING_DEPTH {
+            ordered[..count].sort_by_key(|&position| {
+                let (p, o) = self.board.update(player, opponent, position);
+                self.board.legal_move_bits(o, p).count_ones()
+            });
+        }
+
+        let mut best = f32::NEG_INFINITY;
+        for &position in ordered[..count].iter() {
+            let (p, o) = self.board.update(player, opponent, position);
+            let score = -self.negamax(o, p, depth - 1, -beta, -alpha)?;
+            if score > best {
+                best = score;
+                if score > alpha {
+                    alpha = score;
+                    if alpha >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+
+        Some(best)
+    }
+}
+
+/// Iterative deepening alpha-beta on a pattern evaluation. Every root move
+/// is searched with a full window, so the scores of all of them are exact
+/// at the depth reached.
+pub struct PatternEngine {
+    /// The weights, or why they could not be loaded.
+    evaluator: Result<Arc<Evaluator>, String>,
+    position: Option<BitBoard>,
+    stop: StopHandle,
+}
+
+impl PatternEngine {
+    pub fn new(evaluator: Arc<Evaluator>) -> Self {
+        Self {
+            evaluator: Ok(evaluator),
+            position: None,
+            stop: StopHandle::default(),
+        }
+    }
+
+    /// Loads the weights from `path`. Failing to do so is reported by
+    /// `think`.
+    pub fn from_file(path: &str) -> Self {
+        Self {
+            evaluator: Evaluator::load(path)
+                .map(Arc::new)
+                .map_err(|err| format!("cannot load {}: {}", path, err)),
+            position: None,
+            stop: StopHandle::default(),
+        }
+    }
+}
+
+fn to_score(disks: f32) -> f64 {
+    (disks as f64 / DISKS_PER_SCORE).clamp(-1.0, 1.0)
+}
+
+impl Engine for PatternEngine {
+    fn name(&self) -> String {
+        "pattern".to_owned()
+    }
+
+    fn set_position(&mut self, board: BitBoard) {
+        self.position = Some(board);
+        self.stop.reset();
+    }
+
+    fn think(
+        &mut self,
+        limits: Limits,
+        info: &mut dyn FnMut(&SearchInfo),
+    ) -> Result<u64, EngineError> {
+        let board = self.position.ok_or(EngineError::NoPosition)?;
+        let evaluator = self.evaluator.clone().map_err(EngineError::Crashed)?;
+        let start = Instant::now();
+        let moves = board.moves();
+        if moves.is_empty() {
+            info(&SearchInfo {
+                pv: vec![0],
+                ..Default::default()
+            });
+            return Ok(0);
+        }
+
+        let (player, opponent) = board.curr_board();
+        let empties = (!(player | opponent)).count_ones();
+        if empties <= SOLVE_EMPTIES {
+            if let Some(solution) = board.solve(SOLVE_NODES) {
+                info(&SearchInfo {
+                    best_move: solution.best_move,
+                    score: to_score(solution.score as f32),
+                    iterations: solution.nodes.min(i32::MAX as u64) as i32,
+                    elapsed: start.elapsed().as_millis(),
+                    pv: solution.pv,
+                    moves: vec![MoveStat {
+                        position: solution.best_move,
+                        visits: 1,
+                        score: to_score(solution.score as f32),
+                    }],
+                });
+                return Ok(solution.best_move);
+            }
+        }
+
+        let max_depth = limits.iterations.map_or(60, |iterations| {
+            (iterations / ITERATIONS_PER_DEPTH).clamp(1, 60) as u32
+        });
+        let mut search = Search {
+            evaluator: &evaluator,
+            board,
+            stop: &self.stop,
+            start,
+            time: limits.time,
+            nodes: 0,
+            aborted: false,
+        };
+        // Root moves with their scores at the last completed depth, best
+        // first.
+        let mut scored: Vec<(u64, f32)> = moves.iter().map(|&position| (position, 0.0)).collect();
+        let mut best_move = moves[0];
+        for depth in 1..=max_depth.min(empties) {
+            let mut results = Vec::with_capacity(scored.len());
+            for &(position, _) in scored.iter() {
+                let (p, o) = board.update(player, opponent, position);
+                match search.negamax(o, p, depth - 1, f32::NEG_INFINITY, f32::INFINITY) {
+                    Some(score) => results.push((position, -score)),
+                    None => break,
+                }
+            }
+            if search.aborted {
+                break;
+            }
+            results.sort_by(|a, b| b.1.total_cmp(&a.1));
+            scored = results;
+            best_move = scored[0].0;
+            info(&SearchInfo {
+                best_move,
+                score: to_score(scored[0].1),
+                iterations: search.nodes.min(i32::MAX as u64) as i32,
+                elapsed: start.elapsed().as_millis(),
+                pv: vec![best_move],
+                // Every root move is searched to the same depth, which
+                // stands in for visits.
+                moves: scored
+                    .iter()
+                    .map(|&(position, score)| MoveStat {
+                        position,
+                        visits: depth as usize,
+                        score: to_score(score),
+                    })
+                    .collect(),
+            });
+            // A deeper search would hardly finish in the time left.
+            if limits
+                .time
+                .is_some_and(|time| start.elapsed().as_millis() * 4 > time)
+            {
+                break;
+            }
+        }
+
+        Ok(best_move)
+    }
+
+    fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn searches_to_the_depth_asked() {
+        let mut engine = PatternEngine::new(Arc::new(Evaluator::default()));
+        let board = BitBoard::initial().play(1 << 26);
+        engine.set_position(board);
+        let mut last = SearchInfo::default();
+        let best_move = engine
+            .think(Limits::iterations(3 * ITERATIONS_PER_DEPTH), &mut |i| {
+                last = i.clone()
+            })
+            .unwrap();
+        assert!(board.moves().contains(&best_move));
+        assert_eq!(last.moves.len(), 3);
+        assert!(last.moves.iter().all(|stat| stat.visits == 3));
+
+        let mut missing = PatternEngine::from_file("/nonexistent/weights.bin");
+        missing.set_position(board);
+        assert!(matches!(
+            missing.think(Limits::iterations(1), &mut |_| {}),
+            Err(EngineError::Crashed(_))
+        ));
+    }
+}
@@ -0,0 +1,170 @@
+//! Move notation and a game model shared by the command line tools.
+
+use super::moai::BitBoard;
+
+/// Name of a square in the usual notation, such as `f5`. A pass (`0`) is
+/// `pa`, as in GGF.
+pub fn square_name(position: u64) -> String {
+    if position == 0 {
+        return "pa".to_owned();
+    }
+    let i = 63 - position.trailing_zeros();
+    format!("{}{}", (b'a' + (i % 8) as u8) as char, i / 8 + 1)
+}
+
+/// The inverse of `square_name`, ignoring case. `pass` is accepted too.
+pub fn parse_square(name: &str) -> Option<u64> {
+    let name = name.trim().to_ascii_lowercase();
+    if name == "pa" || name == "pass" {
+        return Some(0);
+    }
+    let bytes = name.as_bytes();
+    if bytes.len() != 2 || !(b'a'..=b'h').contains(&bytes[0]) || !(b'1'..=b'8').contains(&bytes[1])
+    {
+        return None;
+    }
+    let (x, y) = ((bytes[0] - b'a') as u32, (bytes[1] - b'1') as u32);
+
+    Some(1 << (63 - (y * 8 + x)))
+}
+
+/// Whether `position` can be played on `board`: a legal move, or a pass
+/// when the side to move has none and the game is not over.
+pub fn is_legal(board: &BitBoard, position: u64) -> bool {
+    let legal_bits = board.legal_bits();
+    if position == 0 {
+        legal_bits == 0 && !board.is_game_ended()
+    } else {
+        position.count_ones() == 1 && legal_bits & position != 0
+    }
+}
+
+/// A game from an arbitrary start position. Passes are recorded as a `0`
+/// move.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "persistence", derive(serde::Deserialize, serde::Serialize))]
+pub struct Game {
+    pub start: BitBoard,
+    pub moves: Vec<u64>,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new(BitBoard::initial())
+    }
+}
+
+impl Game {
+    pub fn new(start: BitBoard) -> Self {
+        Self {
+            start,
+            moves: Vec::new(),
+        }
+    }
+
+    /// The position after the first `ply` moves.
+    pub fn position_at(&self, ply: usize) -> BitBoard {
+        self.moves
+            .iter()
+            .take(ply)
+            .fold(self.start, |board, &position| board.play(position))
+    }
+
+    /// The current position.
+    pub fn position(&self) -> BitBoard {
+        self.position_at(self.moves.len())
+    }
+
+    pub fn play(&mut self, position: u64) -> Result<(), String> {
+        if !is_legal(&self.position(), position) {
+            return Err(format!("illegal move: {}", square_name(position)));
+        }
+        self.moves.push(position);
+
+        Ok(())
+    }
+
+    /// Plays `position` for `player`, first passing for the other side if it
+    /// is to move and has to pass. Game records often leave such passes out.
+    pub fn play_as(&mut self, player: i32, position: u64) -> Result<(), String> {
+        let board = self.position();
+        if board.player() != player && is_legal(&board, 0) {
+            self.moves.push(0);
+        }
+        if self.position().player() != player {
+            return Err(format!("not {}'s turn", player_name(player)));
+        }
+
+        self.play(position)
+    }
+
+    /// Takes back the last move, if any.
+    pub fn undo(&mut self) -> Option<u64> {
+        self.moves.pop()
+    }
+}
+
+pub fn player_name(player: i32) -> &'static str {
+    match player {
+        0 => "black",
+        1 => "white",
+        _ => "nobody",
+    }
+}
+
+/// Renders `board` as text with coordinates, `X` for black and `O` for white.
+/// Legal moves of the side to move are shown as `*` if `show_moves` is set.
+pub fn board_text(board: &BitBoard, show_moves: bool) -> String {
+    let (black, white) = board.bits();
+    let legal_bits = if show_moves { board.legal_bits() } else { 0 };
+    let mut text = String::from("  a b c d e f g h\n");
+    for y in 0..8 {
+        text.push_str(&format!("{}", y + 1));
+        for x in 0..8 {
+            let bit = 1u64 << (63 - (y * 8 + x));
+            let ch = if black & bit != 0 {
+                'X'
+            } else if white & bit != 0 {
+                'O'
+            } else if legal_bits & bit != 0 {
+                '*'
+            } else {
+                '.'
+            };
+            text.push(' ');
+            text.push(ch);
+        }
+        text.push('\n');
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn square_names_round_trip() {
+        for i in 0..64 {
+            let position = 1 << i;
+            assert_eq!(parse_square(&square_name(position)), Some(position));
+        }
+        assert_eq!(square_name(1 << 63), "a1");
+        assert_eq!(parse_square("F5"), Some(1 << (63 - 37)));
+        assert_eq!(parse_square("pass"), Some(0));
+        assert_eq!(parse_square("i9"), None);
+    }
+
+    #[test]
+    fn play_rejects_illegal_moves_and_inserts_passes() {
+        let mut game = Game::default();
+        assert!(game.play(parse_square("a1").unwrap()).is_err());
+        assert!(game.play(0).is_err());
+        game.play(parse_square("f5").unwrap()).unwrap();
+        assert!(game.play_as(0, parse_square("d6").unwrap()).is_err());
+        game.play_as(1, parse_square("d6").unwrap()).unwrap();
+        assert_eq!(game.undo(), parse_square("d6"));
+        assert_eq!(game.position().player(), 1);
+    }
+}
@@ -0,0 +1,214 @@
+//! Reading and writing games in GGF, the format of the Generic Game Server
+//! also used by NBoard:
+//!
+//! `(;GM[Othello]PB[black]PW[white]RE[+4.000]TY[8]BO[8 -...- *]B[f5]W[d6];)`
+
+use super::game::{parse_square, square_name, Game};
+use super::moai::BitBoard;
+
+/// A game with the GGF properties we keep. Other properties are ignored
+/// when reading.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GgfGame {
+    pub game: Game,
+    /// `PB`
+    pub black: String,
+    /// `PW`
+    pub white: String,
+    /// `PC`, where the game was played.
+    pub place: String,
+    /// `RE`, the disk difference from black's point of view such as
+    /// `+4.000`, or `?` while the game is in progress.
+    pub result: String,
+}
+
+impl GgfGame {
+    pub fn new(game: Game, black: &str, white: &str) -> Self {
+        let mut ggf = Self {
+            game,
+            black: black.to_owned(),
+            white: white.to_owned(),
+            place: "othello".to_owned(),
+            result: String::new(),
+        };
+        ggf.result = ggf.final_result();
+        ggf
+    }
+
+    /// `RE` of the final position, `?` if the game is not over.
+    fn final_result(&self) -> String {
+        let board = self.game.position();
+        if !board.is_game_ended() {
+            return "?".to_owned();
+        }
+        let (black, white) = board.count();
+        format!("{:+.3}", black as f64 - white as f64)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let body = text
+            .strip_prefix("(;")
+            .and_then(|text| text.strip_suffix(";)"))
+            .ok_or("not a GGF game")?;
+
+        let mut properties = Vec::new();
+        let mut rest = body;
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() {
+                break;
+            }
+            let open = rest.find('[').ok_or("missing '['")?;
+            let close = rest[open..].find(']').ok_or("missing ']'")? + open;
+            let key = rest[..open].trim();
+            if key.is_empty() || !key.chars().all(|ch| ch.is_ascii_uppercase()) {
+                return Err(format!("bad property name: {}", key));
+            }
+            properties.push((key, &rest[open + 1..close]));
+            rest = &rest[close + 1..];
+        }
+
+        let mut ggf = Self {
+            game: Game::default(),
+            black: String::new(),
+            white: String::new(),
+            place: String::new(),
+            result: "?".to_owned(),
+        };
+        for (key, value) in properties {
+            match key {
+                "GM" if !value.eq_ignore_ascii_case("othello") => {
+                    return Err(format!("not an Othello game: {}", value));
+                }
+                "TY" if value.trim() != "8" => {
+                    return Err(format!("unsupported game type: {}", value));
+                }
+                "BO" => {
+                    if !ggf.game.moves.is_empty() {
+                        return Err("BO after the first move".to_owned());
+                    }
+                    ggf.game = Game::new(parse_board(value)?);
+                }
+                "B" | "W" => {
+                    let name = value.split('/').next().unwrap();
+                    let position =
+                        parse_square(name).ok_or_else(|| format!("bad move: {}", value))?;
+                    let player = if key == "B" { 0 } else { 1 };
+                    ggf.game.play_as(player, position)?;
+                }
+                "PB" => ggf.black = value.to_owned(),
+                "PW" => ggf.white = value.to_owned(),
+                "PC" => ggf.place = value.to_owned(),
+                "RE" => ggf.result = value.to_owned(),
+                _ => {}
+            }
+        }
+
+        Ok(ggf)
+    }
+}
+
+impl std::fmt::Display for GgfGame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "(;GM[Othello]PC[{}]PB[{}]PW[{}]RE[{}]TY[8]BO[{}]",
+            self.place,
+            self.black,
+            self.white,
+            self.result,
+            board_text(&self.game.start)
+        )?;
+        let mut board = self.game.start;
+        for &position in self.game.moves.iter() {
+            let key = if board.player() == 0 { "B" } else { "W" };
+            write!(f, "{}[{}]", key, square_name(position).to_ascii_uppercase())?;
+            board = board.play(position);
+        }
+        write!(f, ";)")
+    }
+}
+
+/// `BO` value: the size, 64 squares row by row and the side to move, with
+/// `*` for black, `O` for white and `-` for empty.
+pub fn board_text(board: &BitBoard) -> String {
+    let (black, white) = board.bits();
+    let mut text = String::from("8 ");
+    for i in (0..64).rev() {
+        let bit = 1u64 << i;
+        text.push(if black & bit != 0 {
+            '*'
+        } else if white & bit != 0 {
+            'O'
+        } else {
+            '-'
+        });
+    }
+    text.push_str(if board.player() == 0 { " *" } else { " O" });
+
+    text
+}
+
+/// The inverse of `board_text`. Whitespace between squares is allowed.
+pub fn parse_board(text: &str) -> Result<BitBoard, String> {
+    let text = text.trim();
+    let squares = text
+        .strip_prefix('8')
+        .ok_or("only 8x8 boards are supported")?;
+    let squares: Vec<char> = squares.chars().filter(|ch| !ch.is_whitespace()).collect();
+    if squares.len() != 65 {
+        return Err(format!("expected 64 squares and a player: {}", text));
+    }
+
+    let (mut black, mut white) = (0, 0);
+    for (i, ch) in squares[..64].iter().enumerate() {
+        let bit = 1u64 << (63 - i);
+        match ch {
+            '*' | 'X' | 'x' => black |= bit,
+            'O' | 'o' => white |= bit,
+            '-' | '.' => {}
+            _ => return Err(format!("bad square: {}", ch)),
+        }
+    }
+    let player = match squares[64] {
+        '*' | 'X' | 'x' => 0,
+        'O' | 'o' => 1,
+        ch => return Err(format!("bad player: {}", ch)),
+    };
+
+    Ok(BitBoard::from_bits(black, white, player))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NBOARD_GAME: &str = "(;GM[Othello]PC[NBoard]DT[2014-02-21 20:52:27 GMT]PB[./mEdax]\
+        PW[chris]RE[?]TI[5:00]TY[8]BO[8 ---------------------------O*------*O------------\
+        --------------- *]B[F5]W[F6]B[E6/-1.00]W[F4//2.5];)";
+
+    #[test]
+    fn parses_nboard_games() {
+        let ggf = GgfGame::parse(NBOARD_GAME).unwrap();
+        assert_eq!(ggf.black, "./mEdax");
+        assert_eq!(ggf.white, "chris");
+        assert_eq!(ggf.game.start, BitBoard::initial());
+        let moves: Vec<String> = ggf.game.moves.iter().map(|&m| square_name(m)).collect();
+        assert_eq!(moves, ["f5", "f6", "e6", "f4"]);
+    }
+
+    #[test]
+    fn round_trips() {
+        let ggf = GgfGame::parse(NBOARD_GAME).unwrap();
+        let copy = GgfGame::parse(&ggf.to_string()).unwrap();
+        assert_eq!(copy.game, ggf.game);
+        assert_eq!(copy.black, ggf.black);
+    }
+
+    #[test]
+    fn rejects_illegal_moves() {
+        let text = NBOARD_GAME.replace("W[F4//2.5]", "W[A1]");
+        assert!(GgfGame::parse(&text).is_err());
+    }
+}
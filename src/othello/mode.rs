@@ -0,0 +1,121 @@
+//! Who plays each colour in the GUI, and the AI settings of each side.
+
+use super::difficulty::Difficulty;
+use super::engine::{Engine, EngineKind, Limits};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "persistence", derive(serde::Deserialize, serde::Serialize))]
+pub enum Controller {
+    Human,
+    Ai,
+}
+
+impl Controller {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Controller::Human => "Human",
+            Controller::Ai => "AI",
+        }
+    }
+}
+
+/// How the AI of one side plays.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "persistence", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "persistence", serde(default))]
+pub struct AiSettings {
+    /// `None` means the custom engine below is used.
+    pub difficulty: Option<Difficulty>,
+    pub custom_engine: EngineKind,
+    /// Milliseconds per move of the custom engine.
+    pub think_time: u64,
+}
+
+impl Default for AiSettings {
+    fn default() -> Self {
+        Self {
+            difficulty: Some(Difficulty::Advanced),
+            custom_engine: EngineKind::Mcts(Default::default()),
+            think_time: 1000,
+        }
+    }
+}
+
+impl AiSettings {
+    /// Identifies the configured engine, so that a new one is created only
+    /// when the settings change.
+    pub fn engine_spec(&self) -> String {
+        match self.difficulty {
+            Some(difficulty) => format!("level:{}", difficulty.name()),
+            None => self.custom_engine.spec(),
+        }
+    }
+
+    pub fn create_engine(&self) -> Box<dyn Engine> {
+        match self.difficulty {
+            Some(difficulty) => difficulty.engine(),
+            None => self.custom_engine.create(),
+        }
+    }
+
+    pub fn limits(&self) -> Limits {
+        match self.difficulty {
+            Some(difficulty) => difficulty.limits(),
+            None => Limits::time(self.think_time as u128),
+        }
+    }
+}
+
+/// The players of a game, indexed by colour (0 is black).
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "persistence", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "persistence", serde(default))]
+pub struct GameMode {
+    pub controllers: [Controller; 2],
+    /// Used by the sides the AI plays.
+    pub ai: [AiSettings; 2],
+    /// Whether moves can be taken back, or played again from an earlier one.
+    pub allow_undo: bool,
+}
+
+impl Default for GameMode {
+    /// A human with black against the AI.
+    fn default() -> Self {
+        Self {
+            controllers: [Controller::Human, Controller::Ai],
+            ai: Default::default(),
+            allow_undo: true,
+        }
+    }
+}
+
+impl GameMode {
+    pub fn is_ai(&self, player: i32) -> bool {
+        self.controllers[player as usize] == Controller::Ai
+    }
+
+    pub fn is_human(&self, player: i32) -> bool {
+        self.controllers[player as usize] == Controller::Human
+    }
+
+    pub fn has_human(&self) -> bool {
+        self.controllers.contains(&Controller::Human)
+    }
+
+    /// Name of the player of `side` in game records.
+    pub fn player_name(&self, side: usize) -> String {
+        match self.controllers[side] {
+            Controller::Human => "human".to_owned(),
+            Controller::Ai => self.ai[side].engine_spec(),
+        }
+    }
+
+    /// Such as `Human vs AI`, black first.
+    pub fn name(&self) -> String {
+        format!(
+            "{} vs {}",
+            self.controllers[0].name(),
+            self.controllers[1].name()
+        )
+    }
+}
@@ -0,0 +1,626 @@
+//! Two-player games over TCP. One side hosts on a port and the other joins
+//! it. Both sides keep the whole game and check every move with
+//! `Board::is_legal_move`; the host's copy wins when they reconnect.
+//!
+//! The protocol is one message per line:
+//!
+//! * `HELLO othello <version>`, sent by both sides on connecting
+//! * `GAME <colour> <moves>...`, from the host: the colour of the receiver
+//!   (`black` or `white`) and the moves so far, passes included
+//! * `MOVE <ply> <square>`, a move such as `MOVE 0 f5`; passes are played
+//!   implicitly by both sides and never sent
+//! * `NEW`, asks the host for a new game
+//! * `ERROR <reason>`, sent before closing the connection, and instead of
+//!   `HELLO` to a peer joining while another one plays and a third waits
+
+use super::board::Board;
+use super::game::{parse_square, square_name};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+/// Bumped whenever the messages change.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+pub const DEFAULT_PORT: u16 = 7878;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest line accepted, in bytes. A whole game fits easily.
+const MAX_LINE: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    Hello { version: u32 },
+    Game { colour: i32, moves: Vec<u64> },
+    Move { ply: usize, position: u64 },
+    New,
+    Error(String),
+}
+
+impl Message {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let bad = || format!("bad message: {}", line);
+        let message = match words.next() {
+            Some("HELLO") => {
+                if words.next() != Some("othello") {
+                    return Err(bad());
+                }
+                let version = words.next().and_then(|v| v.parse().ok()).ok_or_else(bad)?;
+                Message::Hello { version }
+            }
+            Some("GAME") => {
+                let colour = match words.next() {
+                    Some("black") => 0,
+                    Some("white") => 1,
+                    _ => return Err(bad()),
+                };
+                let moves = words
+                    .map(parse_square)
+                    .collect::<Option<Vec<u64>>>()
+                    .ok_or_else(bad)?;
+                Message::Game { colour, moves }
+            }
+            Some("MOVE") => {
+                let ply = words.next().and_then(|p| p.parse().ok()).ok_or_else(bad)?;
+                let position = words.next().and_then(parse_square).ok_or_else(bad)?;
+                Message::Move { ply, position }
+            }
+            Some("NEW") => Message::New,
+            Some("ERROR") => Message::Error(words.collect::<Vec<&str>>().join(" ")),
+            _ => return Err(bad()),
+        };
+
+        Ok(message)
+    }
+
+    pub fn to_line(&self) -> String {
+        match self {
+            Message::Hello { version } => format!("HELLO othello {}", version),
+            Message::Game { colour, moves } => {
+                let mut line = String::from(if *colour == 0 {
+                    "GAME black"
+                } else {
+                    "GAME white"
+                });
+                for &position in moves.iter() {
+                    line.push(' ');
+                    line.push_str(&square_name(position));
+                }
+                line
+            }
+            Message::Move { ply, position } => format!("MOVE {} {}", ply, square_name(*position)),
+            Message::New => "NEW".to_owned(),
+            Message::Error(reason) => format!("ERROR {}", reason),
+        }
+    }
+}
+
+/// Whether neither side can move.
+pub fn is_over(board: &Board) -> bool {
+    let mut passed = board.clone();
+    passed.player = passed.next_player();
+    board.legal_moves().is_empty() && passed.legal_moves().is_empty()
+}
+
+/// Plays `position` on `board` if `Board::is_legal_move` allows it, `0`
+/// being a pass, which is legal only when there is no other move.
+pub fn apply(board: &Board, position: u64) -> Option<Board> {
+    if position == 0 {
+        if !board.legal_moves().is_empty() || is_over(board) {
+            return None;
+        }
+        let mut passed = board.clone();
+        passed.player = passed.next_player();
+        return Some(passed);
+    }
+    let i = 63 - position.trailing_zeros() as usize;
+    let (x, y) = (i % 8, i / 8);
+    if position.count_ones() != 1 || !board.is_legal_move(board.player_disk(), x, y) {
+        return None;
+    }
+
+    Some(board.play(x, y))
+}
+
+enum Event {
+    Message(Message),
+    Closed(String),
+}
+
+struct Connection {
+    stream: TcpStream,
+    events: Receiver<Event>,
+    /// Set once the peer's `HELLO` was accepted.
+    greeted: bool,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let (sender, events) = mpsc::channel();
+        thread::spawn(move || loop {
+            let mut line = String::new();
+            let event = match (&mut reader).take(MAX_LINE as u64).read_line(&mut line) {
+                Ok(0) => Event::Closed("connection closed".to_owned()),
+                Ok(len) if len == MAX_LINE && !line.ends_with('\n') => {
+                    Event::Closed("line too long".to_owned())
+                }
+                Ok(_) => match Message::parse(&line) {
+                    Ok(message) => Event::Message(message),
+                    Err(err) => Event::Closed(err),
+                },
+                Err(err) => Event::Closed(err.to_string()),
+            };
+            let closed = matches!(event, Event::Closed(_));
+            if sender.send(event).is_err() || closed {
+                return;
+            }
+        });
+        let mut connection = Self {
+            stream,
+            events,
+            greeted: false,
+        };
+        connection.send(&Message::Hello {
+            version: PROTOCOL_VERSION,
+        })?;
+
+        Ok(connection)
+    }
+
+    fn send(&mut self, message: &Message) -> io::Result<()> {
+        writeln!(self.stream, "{}", message.to_line())?;
+        self.stream.flush()
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(std::net::Shutdown::Both);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// Hosting, waiting for someone to join.
+    Listening(SocketAddr),
+    Connecting,
+    /// The game can be played.
+    Connected,
+    Disconnected(String),
+}
+
+/// One side of a network game.
+pub struct NetSession {
+    host: bool,
+    /// Where a joining session connects to.
+    address: String,
+    /// Colour played on this side.
+    pub colour: i32,
+    /// Moves of the game, passes included.
+    pub moves: Vec<u64>,
+    board: Board,
+    status: Status,
+    connection: Option<Connection>,
+    /// New connections: accepted ones when hosting, the result of
+    /// connecting when joining.
+    incoming: Option<Receiver<io::Result<TcpStream>>>,
+    /// A peer that connected while another one played, let in once that
+    /// one leaves: it is likely the same peer coming back from a
+    /// connection the host hasn't seen drop yet. Others are refused.
+    waiting: Option<TcpStream>,
+}
+
+impl NetSession {
+    /// Listens on `port` (0 picks a free one) and plays `colour`.
+    pub fn host(port: u16, colour: i32) -> io::Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        let address = listener.local_addr()?;
+        let (sender, incoming) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if sender.send(stream).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(Self {
+            host: true,
+            address: address.to_string(),
+            colour,
+            moves: Vec::new(),
+            board: Board::default(),
+            status: Status::Listening(address),
+            connection: None,
+            incoming: Some(incoming),
+            waiting: None,
+        })
+    }
+
+    /// Connects to a host at `address`, such as `192.168.1.2:7878`. The
+    /// host decides the colours and sends the game.
+    pub fn join(address: &str) -> Self {
+        let mut session = Self {
+            host: false,
+            address: address.to_owned(),
+            colour: 1,
+            moves: Vec::new(),
+            board: Board::default(),
+            status: Status::Connecting,
+            connection: None,
+            incoming: None,
+            waiting: None,
+        };
+        session.reconnect();
+        session
+    }
+
+    /// Connects again after a disconnection. Only the joining side does
+    /// anything; the host keeps listening all the time.
+    pub fn reconnect(&mut self) {
+        if self.host || self.connection.is_some() {
+            return;
+        }
+        let address = self.address.clone();
+        let (sender, incoming) = mpsc::channel();
+        thread::spawn(move || {
+            let result = address
+                .to_socket_addrs()
+                .and_then(|mut addresses| {
+                    addresses
+                        .next()
+                        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "unknown address"))
+                })
+                .and_then(|address| TcpStream::connect_timeout(&address, CONNECT_TIMEOUT));
+            let _ = sender.send(result);
+        });
+        self.incoming = Some(incoming);
+        self.status = Status::Connecting;
+    }
+
+    pub fn is_host(&self) -> bool {
+        self.host
+    }
+
+    pub fn status(&self) -> &Status {
+        &self.status
+    }
+
+    /// The current position.
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// Whether the local side may move now.
+    pub fn is_my_turn(&self) -> bool {
+        self.status == Status::Connected
+            && self.board.player == self.colour
+            && !is_over(&self.board)
+    }
+
+    /// Handles the network; returns true if the game changed.
+    pub fn poll(&mut self) -> bool {
+        self.accept();
+        let mut changed = false;
+        loop {
+            let event = match self.connection.as_ref().map(|c| c.events.try_recv()) {
+                Some(Ok(event)) => event,
+                Some(Err(TryRecvError::Disconnected)) => {
+                    Event::Closed("connection lost".to_owned())
+                }
+                Some(Err(TryRecvError::Empty)) | None => break,
+            };
+            match event {
+                Event::Message(message) => match self.receive(message) {
+                    Ok(game_changed) => changed |= game_changed,
+                    Err(reason) => {
+                        if let Some(connection) = self.connection.as_mut() {
+                            let _ = connection.send(&Message::Error(reason.clone()));
+                        }
+                        self.disconnect(reason);
+                    }
+                },
+                Event::Closed(reason) => self.disconnect(reason),
+            }
+        }
+
+        changed
+    }
+
+    fn accept(&mut self) {
+        if let Some(stream) = self.incoming.as_ref().and_then(|i| i.try_recv().ok()) {
+            if !self.host {
+                self.incoming = None;
+            }
+            match stream {
+                Ok(stream) if self.waiting.is_none() => self.waiting = Some(stream),
+                Ok(stream) => refuse(stream, "a game is already in progress"),
+                Err(err) => return self.disconnect(err.to_string()),
+            }
+        }
+        if self.connection.is_some() {
+            return;
+        }
+        let stream = match self.waiting.take() {
+            Some(stream) => stream,
+            None => return,
+        };
+        match Connection::new(stream) {
+            Ok(connection) => {
+                self.connection = Some(connection);
+                self.status = Status::Connecting;
+            }
+            Err(err) => self.disconnect(err.to_string()),
+        }
+    }
+
+    fn disconnect(&mut self, reason: String) {
+        self.connection = None;
+        self.status = Status::Disconnected(reason);
+    }
+
+    fn send(&mut self, message: &Message) {
+        let result = match self.connection.as_mut() {
+            Some(connection) => connection.send(message),
+            None => return,
+        };
+        if let Err(err) = result {
+            self.disconnect(err.to_string());
+        }
+    }
+
+    /// Handles a message from the peer; returns true if the game changed,
+    /// or the reason to drop the connection.
+    fn receive(&mut self, message: Message) -> Result<bool, String> {
+        let greeted = self.connection.as_ref().is_some_and(|c| c.greeted);
+        match message {
+            Message::Hello { version } => {
+                if version != PROTOCOL_VERSION {
+                    return Err(format!(
+                        "protocol version {} is not supported (expected {})",
+                        version, PROTOCOL_VERSION
+                    ));
+                }
+                if let Some(connection) = self.connection.as_mut() {
+                    connection.greeted = true;
+                }
+                if self.host {
+                    self.status = Status::Connected;
+                    self.send_game();
+                }
+                Ok(false)
+            }
+            Message::Error(reason) => Err(format!("peer: {}", reason)),
+            _ if !greeted => Err("expected HELLO".to_owned()),
+            Message::Game { colour, moves } if !self.host => {
+                self.board = replay(&moves).ok_or("invalid game")?;
+                self.moves = moves;
+                self.colour = colour;
+                self.status = Status::Connected;
+                Ok(true)
+            }
+            Message::Move { ply, position } => {
+                if self.status != Status::Connected || self.board.player == self.colour {
+                    return Err("not your turn".to_owned());
+                }
+                if ply != self.moves.len() {
+                    return Err(format!("expected move {}, got {}", self.moves.len(), ply));
+                }
+                self.push(position)
+                    .ok_or_else(|| format!("illegal move {}", square_name(position)))?;
+                Ok(true)
+            }
+            Message::New if self.host => {
+                self.new_game();
+                Ok(true)
+            }
+            message => Err(format!("unexpected {}", message.to_line())),
+        }
+    }
+
+    fn send_game(&mut self) {
+        let message = Message::Game {
+            colour: 1 - self.colour,
+            moves: self.moves.clone(),
+        };
+        self.send(&message);
+    }
+
+    /// Plays `position` and the passes that follow it.
+    fn push(&mut self, position: u64) -> Option<()> {
+        self.board = apply(&self.board, position)?;
+        self.moves.push(position);
+        while let Some(board) = apply(&self.board, 0) {
+            self.board = board;
+            self.moves.push(0);
+        }
+
+        Some(())
+    }
+
+    /// Plays a move of the local side and sends it.
+    pub fn play(&mut self, position: u64) -> Result<(), String> {
+        if !self.is_my_turn() {
+            return Err("not your turn".to_owned());
+        }
+        let ply = self.moves.len();
+        self.push(position)
+            .ok_or_else(|| format!("illegal move {}", square_name(position)))?;
+        self.send(&Message::Move { ply, position });
+
+        Ok(())
+    }
+
+    /// Starts a new game. The host starts it at once and tells the peer;
+    /// the joining side asks the host.
+    pub fn new_game(&mut self) {
+        if self.host {
+            self.moves.clear();
+            self.board = Board::default();
+            self.send_game();
+        } else {
+            self.send(&Message::New);
+        }
+    }
+}
+
+/// Sends `reason` to a peer that is not let in. Its `HELLO` is read before
+/// closing: closing with unread data resets the connection, and the peer
+/// may lose the reason.
+fn refuse(mut stream: TcpStream, reason: &str) {
+    let line = Message::Error(reason.to_owned()).to_line();
+    thread::spawn(move || {
+        let _ = writeln!(stream, "{}", line);
+        let _ = stream.shutdown(std::net::Shutdown::Write);
+        let _ = stream.set_read_timeout(Some(CONNECT_TIMEOUT));
+        let _ = io::copy(&mut stream.take(MAX_LINE as u64), &mut io::sink());
+    });
+}
+
+/// The position after `moves` from the start, checking every move.
+fn replay(moves: &[u64]) -> Option<Board> {
+    let mut board = Board::default();
+    for &position in moves.iter() {
+        board = apply(&board, position)?;
+    }
+
+    Some(board)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    /// Polls both sessions until `done` holds, failing after a few seconds.
+    fn wait(
+        a: &mut NetSession,
+        b: &mut NetSession,
+        done: impl Fn(&NetSession, &NetSession) -> bool,
+    ) {
+        let start = Instant::now();
+        while !done(a, b) {
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            a.poll();
+            b.poll();
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn port(session: &NetSession) -> u16 {
+        match session.status() {
+            Status::Listening(address) => address.port(),
+            status => panic!("not listening: {:?}", status),
+        }
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = [
+            Message::Hello { version: 1 },
+            Message::Game {
+                colour: 1,
+                moves: vec![parse_square("f5").unwrap(), 0],
+            },
+            Message::Move {
+                ply: 3,
+                position: parse_square("c4").unwrap(),
+            },
+            Message::New,
+            Message::Error("bad things".to_owned()),
+        ];
+        for message in messages.iter() {
+            assert_eq!(&Message::parse(&message.to_line()).unwrap(), message);
+        }
+        assert!(Message::parse("MOVE x f5").is_err());
+    }
+
+    #[test]
+    fn plays_and_resumes_on_localhost() {
+        let f5 = parse_square("f5").unwrap();
+        let d6 = parse_square("d6").unwrap();
+        let mut host = NetSession::host(0, 0).unwrap();
+        let address = format!("127.0.0.1:{}", port(&host));
+        let mut guest = NetSession::join(&address);
+        wait(&mut host, &mut guest, |h, g| {
+            h.status() == &Status::Connected && g.status() == &Status::Connected
+        });
+        assert_eq!(guest.colour, 1);
+        assert!(guest.play(f5).is_err());
+        host.play(f5).unwrap();
+        wait(&mut host, &mut guest, |_, g| g.moves.len() == 1);
+        assert!(guest.play(parse_square("a1").unwrap()).is_err());
+        guest.play(d6).unwrap();
+        wait(&mut host, &mut guest, |h, _| h.moves.len() == 2);
+        assert_eq!(host.board().disks, guest.board().disks);
+
+        // The guest drops out and comes back to the same game.
+        drop(guest);
+        let mut guest = NetSession::join(&address);
+        wait(&mut host, &mut guest, |h, g| {
+            h.status() == &Status::Connected && g.moves.len() == 2
+        });
+        assert_eq!(host.board().disks, guest.board().disks);
+    }
+
+    #[test]
+    fn drops_peers_sending_illegal_moves() {
+        let mut host = NetSession::host(0, 1).unwrap();
+        let mut stream = TcpStream::connect(("127.0.0.1", port(&host))).unwrap();
+        writeln!(stream, "HELLO othello {}", PROTOCOL_VERSION).unwrap();
+        writeln!(stream, "MOVE 0 a1").unwrap();
+        let mut idle = NetSession::join("127.0.0.1:1");
+        wait(
+            &mut host,
+            &mut idle,
+            |h, _| matches!(h.status(), Status::Disconnected(reason) if reason.contains("illegal")),
+        );
+        assert!(host.moves.is_empty());
+    }
+
+    #[test]
+    fn queues_a_second_peer_and_refuses_others() {
+        let mut host = NetSession::host(0, 0).unwrap();
+        let address = format!("127.0.0.1:{}", port(&host));
+        let mut guest = NetSession::join(&address);
+        wait(&mut host, &mut guest, |h, g| {
+            h.status() == &Status::Connected && g.status() == &Status::Connected
+        });
+        let mut second = NetSession::join(&address);
+        wait(&mut host, &mut second, |h, _| h.waiting.is_some());
+        let mut third = NetSession::join(&address);
+        wait(
+            &mut host,
+            &mut third,
+            |_, t| matches!(t.status(), Status::Disconnected(reason) if reason.contains("in progress")),
+        );
+        host.play(parse_square("f5").unwrap()).unwrap();
+        wait(&mut host, &mut guest, |_, g| g.moves.len() == 1);
+        assert_eq!(second.status(), &Status::Connecting);
+
+        // The second peer takes over once the first one leaves.
+        drop(guest);
+        wait(&mut host, &mut second, |h, s| {
+            h.status() == &Status::Connected && s.moves.len() == 1
+        });
+    }
+
+    #[test]
+    fn drops_peers_sending_long_lines() {
+        let mut host = NetSession::host(0, 0).unwrap();
+        let mut stream = TcpStream::connect(("127.0.0.1", port(&host))).unwrap();
+        writeln!(stream, "HELLO othello {}", PROTOCOL_VERSION).unwrap();
+        write!(stream, "MOVE 0 {}", "f5 ".repeat(MAX_LINE)).unwrap();
+        let mut idle = NetSession::join("127.0.0.1:1");
+        wait(
+            &mut host,
+            &mut idle,
+            |h, _| matches!(h.status(), Status::Disconnected(reason) if reason.contains("too long")),
+        );
+    }
+}
@@ -0,0 +1,469 @@
+//! A small neural network run on the CPU: the board as planes in, a policy
+//! over the squares and the value of the position out. `MCTS` can use it
+//! for its move priors and leaf values instead of random playouts (see
+//! `MCTS::with_network`), and `training` improves it by self-play.
+//!
+//! The input is three planes of 64 squares, from the side to move's point
+//! of view: its disks, the opponent's disks and the legal moves. Two fully
+//! connected hidden layers with ReLU feed a policy head, a softmax over the
+//! legal moves, and a value head, a tanh from -1 (loss) to 1 (win).
+//!
+//! Weights are stored in a binary file: the magic bytes `OTNN`, the format
+//! version, `u16`, two reserved bytes and the size of the hidden layers,
+//! `u32`, then the weights and biases of each layer as `f32`. All numbers
+//! are little-endian.
+
+pub mod training;
+
+use super::moai::BitBoard;
+use super::selfplay::Sample;
+use rand::prelude::*;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+pub const MAGIC: &[u8; 4] = b"OTNN";
+
+/// Bumped whenever the file or the architecture changes.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// Size of the hidden layers of `Network::default`.
+pub const DEFAULT_HIDDEN: usize = 128;
+
+const INPUTS: usize = 3 * 64;
+
+/// Largest hidden layer accepted from a file.
+const MAX_HIDDEN: usize = 4096;
+
+/// Weight decay applied at each training step.
+const WEIGHT_DECAY: f32 = 1e-4;
+
+/// A fully connected layer, its weights stored output by output.
+#[derive(Clone, Debug, PartialEq)]
+struct Layer {
+    inputs: usize,
+    outputs: usize,
+    weights: Vec<f32>,
+    biases: Vec<f32>,
+}
+
+impl Layer {
+    fn zeros(inputs: usize, outputs: usize) -> Self {
+        Self {
+            inputs,
+            outputs,
+            weights: vec![0.0; inputs * outputs],
+            biases: vec![0.0; outputs],
+        }
+    }
+
+    /// He initialisation, for layers followed by a ReLU.
+    fn random(inputs: usize, outputs: usize, rng: &mut StdRng) -> Self {
+        let bound = (6.0 / inputs as f32).sqrt();
+        let mut layer = Self::zeros(inputs, outputs);
+        for weight in layer.weights.iter_mut() {
+            *weight = rng.gen_range(-bound..bound);
+        }
+        layer
+    }
+
+    fn forward(&self, input: &[f32]) -> Vec<f32> {
+        self.weights
+            .chunks_exact(self.inputs)
+            .zip(self.biases.iter())
+            .map(|(row, bias)| bias + row.iter().zip(input).map(|(w, x)| w * x).sum::<f32>())
+            .collect()
+    }
+
+    /// Adds to `gradient` the gradient of the loss for `input`, given
+    /// `output_gradient`, and returns the gradient of the input.
+    fn backward(&self, input: &[f32], output_gradient: &[f32], gradient: &mut Layer) -> Vec<f32> {
+        let mut input_gradient = vec![0.0; self.inputs];
+        for (j, &dy) in output_gradient.iter().enumerate() {
+            if dy == 0.0 {
+                continue;
+            }
+            gradient.biases[j] += dy;
+            let row = j * self.inputs..(j + 1) * self.inputs;
+            for ((g, w), (x, dx)) in gradient.weights[row.clone()]
+                .iter_mut()
+                .zip(self.weights[row].iter())
+                .zip(input.iter().zip(input_gradient.iter_mut()))
+            {
+                *g += dy * x;
+                *dx += dy * w;
+            }
+        }
+        input_gradient
+    }
+
+    /// Takes a step of `rate` against the mean of `count` gradients.
+    fn descend(&mut self, gradient: &Layer, rate: f32, count: usize) {
+        let scale = rate / count as f32;
+        for (w, g) in self.weights.iter_mut().zip(gradient.weights.iter()) {
+            *w -= scale * g + rate * WEIGHT_DECAY * *w;
+        }
+        for (b, g) in self.biases.iter_mut().zip(gradient.biases.iter()) {
+            *b -= scale * g;
+        }
+    }
+}
+
+fn relu(values: &mut [f32]) {
+    for value in values.iter_mut() {
+        *value = value.max(0.0);
+    }
+}
+
+/// The bit of square `bit` under one of the 8 symmetries of the board.
+fn transform_bit(bit: u32, symmetry: usize) -> u32 {
+    let (x, y) = (7 - bit % 8, 7 - bit / 8);
+    let (x, y) = if symmetry & 4 != 0 { (y, x) } else { (x, y) };
+    let x = if symmetry & 1 != 0 { 7 - x } else { x };
+    let y = if symmetry & 2 != 0 { 7 - y } else { y };
+    63 - (y * 8 + x)
+}
+
+fn transform(bits: u64, symmetry: usize) -> u64 {
+    (0..64)
+        .filter(|&bit| bits & (1 << bit) != 0)
+        .fold(0, |out, bit| out | 1 << transform_bit(bit, symmetry))
+}
+
+/// A position with the policy and value the network should give it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Example {
+    pub board: BitBoard,
+    /// Probability of each legal move.
+    pub policy: Vec<(u64, f32)>,
+    /// From -1 to 1, for the side to move.
+    pub value: f32,
+}
+
+impl Example {
+    /// Learns the search's visits and the sign of the result of the game.
+    /// Samples without visits learn the move played.
+    pub fn from_sample(sample: &Sample) -> Self {
+        let total: u32 = sample.visits.iter().map(|&(_, n)| n).sum();
+        let policy = if total == 0 {
+            vec![(sample.played, 1.0)]
+        } else {
+            sample
+                .visits
+                .iter()
+                .map(|&(position, n)| (position, n as f32 / total as f32))
+                .collect()
+        };
+
+        Self {
+            board: sample.board,
+            policy,
+            value: (sample.result as f32).signum(),
+        }
+    }
+
+    /// The same example under one of the 8 symmetries of the board.
+    fn transformed(&self, symmetry: usize) -> Self {
+        let (black, white) = self.board.bits();
+        Self {
+            board: BitBoard::from_bits(
+                transform(black, symmetry),
+                transform(white, symmetry),
+                self.board.player(),
+            ),
+            policy: self
+                .policy
+                .iter()
+                .map(|&(position, p)| (transform(position, symmetry), p))
+                .collect(),
+            value: self.value,
+        }
+    }
+}
+
+/// Mean losses over a batch.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Loss {
+    /// Squared error of the value.
+    pub value: f64,
+    /// Cross-entropy of the policy.
+    pub policy: f64,
+}
+
+/// Values computed by a forward pass, kept for the backward pass.
+struct Pass {
+    input: Vec<f32>,
+    hidden: [Vec<f32>; 2],
+    /// Softmax over the legal moves, 0 elsewhere.
+    policy: Vec<f32>,
+    value: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Network {
+    /// Two hidden layers, then the policy and value heads.
+    layers: [Layer; 4],
+}
+
+impl Default for Network {
+    /// A randomly initialised network with `DEFAULT_HIDDEN` units per layer.
+    fn default() -> Self {
+        Self::new(DEFAULT_HIDDEN, 0)
+    }
+}
+
+impl Network {
+    /// A randomly initialised network with `hidden` units per hidden layer.
+    pub fn new(hidden: usize, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        Self {
+            layers: [
+                Layer::random(INPUTS, hidden, &mut rng),
+                Layer::random(hidden, hidden, &mut rng),
+                Layer::random(hidden, 64, &mut rng),
+                Layer::random(hidden, 1, &mut rng),
+            ],
+        }
+    }
+
+    pub fn hidden(&self) -> usize {
+        self.layers[0].outputs
+    }
+
+    fn zeros(hidden: usize) -> Self {
+        Self {
+            layers: [
+                Layer::zeros(INPUTS, hidden),
+                Layer::zeros(hidden, hidden),
+                Layer::zeros(hidden, 64),
+                Layer::zeros(hidden, 1),
+            ],
+        }
+    }
+
+    pub fn read<R: Read>(mut input: R) -> io::Result<Self> {
+        let mut header = [0; 12];
+        input.read_exact(&mut header)?;
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        if &header[..4] != MAGIC {
+            return Err(invalid("not a network file".to_owned()));
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version != FORMAT_VERSION {
+            return Err(invalid(format!("unsupported format version {}", version)));
+        }
+        let hidden = u32::from_le_bytes(header[8..].try_into().unwrap()) as usize;
+        if hidden == 0 || hidden > MAX_HIDDEN {
+            return Err(invalid(format!("bad hidden layer size {}", hidden)));
+        }
+
+        let mut network = Self::zeros(hidden);
+        for layer in network.layers.iter_mut() {
+            for values in [&mut layer.weights, &mut layer.biases] {
+                let mut bytes = vec![0; values.len() * 4];
+                input.read_exact(&mut bytes)?;
+                for (value, bytes) in values.iter_mut().zip(bytes.chunks_exact(4)) {
+                    *value = f32::from_le_bytes(bytes.try_into().unwrap());
+                }
+            }
+        }
+
+        Ok(network)
+    }
+
+    pub fn write<W: Write>(&self, mut out: W) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        out.write_all(&[0, 0])?;
+        out.write_all(&(self.hidden() as u32).to_le_bytes())?;
+        let mut bytes = Vec::new();
+        for layer in self.layers.iter() {
+            for value in layer.weights.iter().chain(layer.biases.iter()) {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        out.write_all(&bytes)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write(&mut out)?;
+        out.flush()
+    }
+
+    fn forward(&self, board: &BitBoard) -> Pass {
+        let (player, opponent) = board.curr_board();
+        let legal = board.legal_bits();
+        let mut input = vec![0.0; INPUTS];
+        for (plane, bits) in [player, opponent, legal].iter().enumerate() {
+            for bit in 0..64 {
+                if bits & (1 << bit) != 0 {
+                    input[plane * 64 + bit] = 1.0;
+                }
+            }
+        }
+
+        let mut first = self.layers[0].forward(&input);
+        relu(&mut first);
+        let mut second = self.layers[1].forward(&first);
+        relu(&mut second);
+        let logits = self.layers[2].forward(&second);
+        let value = self.layers[3].forward(&second)[0].tanh();
+
+        let max = (0..64)
+            .filter(|&bit| legal & (1 << bit) != 0)
+            .map(|bit| logits[bit])
+            .fold(f32::NEG_INFINITY, f32::max);
+        let mut policy: Vec<f32> = (0..64)
+            .map(|bit| {
+                if legal & (1 << bit) != 0 {
+                    (logits[bit] - max).exp()
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let sum: f32 = policy.iter().sum();
+        if sum > 0.0 {
+            policy.iter_mut().for_each(|p| *p /= sum);
+        }
+
+        Pass {
+            input,
+            hidden: [first, second],
+            policy,
+            value,
+        }
+    }
+
+    /// The probability of each legal move and the value for the side to
+    /// move, from -1 to 1.
+    pub fn evaluate(&self, board: &BitBoard) -> (Vec<(u64, f32)>, f32) {
+        let pass = self.forward(board);
+        let priors = board
+            .moves()
+            .into_iter()
+            .map(|position| (position, pass.policy[position.trailing_zeros() as usize]))
+            .collect();
+        (priors, pass.value)
+    }
+
+    /// Takes one gradient descent step of `rate` on `batch`, each example
+    /// seen under a random symmetry of the board, and returns the losses
+    /// before the step.
+    pub fn train(&mut self, batch: &[Example], rate: f32, rng: &mut impl Rng) -> Loss {
+        let mut gradient = Self::zeros(self.hidden());
+        let mut loss = Loss::default();
+        for example in batch.iter() {
+            let example = example.transformed(rng.gen_range(0..8));
+            let pass = self.forward(&example.board);
+
+            let error = pass.value - example.value;
+            loss.value += (error as f64).powi(2);
+            let value_gradient = [2.0 * error * (1.0 - pass.value * pass.value)];
+            let mut policy_gradient = pass.policy.clone();
+            for &(position, p) in example.policy.iter() {
+                let bit = position.trailing_zeros() as usize;
+                if position != 0 && pass.policy[bit] > 0.0 {
+                    loss.policy -= p as f64 * (pass.policy[bit] as f64).ln();
+                    policy_gradient[bit] -= p;
+                }
+            }
+
+            let [first, second] = &pass.hidden;
+            let [g0, g1, g2, g3] = &mut gradient.layers;
+            let mut hidden_gradient = self.layers[2].backward(second, &policy_gradient, g2);
+            let from_value = self.layers[3].backward(second, &value_gradient, g3);
+            for ((g, v), x) in hidden_gradient.iter_mut().zip(from_value).zip(second) {
+                *g = if *x > 0.0 { *g + v } else { 0.0 };
+            }
+            let mut hidden_gradient = self.layers[1].backward(first, &hidden_gradient, g1);
+            for (g, x) in hidden_gradient.iter_mut().zip(first) {
+                if *x <= 0.0 {
+                    *g = 0.0;
+                }
+            }
+            self.layers[0].backward(&pass.input, &hidden_gradient, g0);
+        }
+
+        for (layer, gradient) in self.layers.iter_mut().zip(gradient.layers.iter()) {
+            layer.descend(gradient, rate, batch.len().max(1));
+        }
+        let count = batch.len().max(1) as f64;
+        Loss {
+            value: loss.value / count,
+            policy: loss.policy / count,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symmetries_map_the_board_onto_itself() {
+        let board = BitBoard::initial().play(1 << 26);
+        let (black, white) = board.bits();
+        for symmetry in 0..8 {
+            let example = Example {
+                board,
+                policy: board.moves().into_iter().map(|m| (m, 1.0)).collect(),
+                value: 0.0,
+            }
+            .transformed(symmetry);
+            let (b, w) = example.board.bits();
+            assert_eq!((b.count_ones(), w.count_ones()), (4, 1));
+            // Legal moves are legal in the transformed board.
+            let legal = example.board.legal_bits();
+            assert!(example.policy.iter().all(|&(m, _)| legal & m != 0));
+            assert_eq!(legal.count_ones(), 3);
+        }
+        assert_eq!(transform(black, 0), black);
+        assert_eq!(transform(white, 0), white);
+    }
+
+    #[test]
+    fn weights_round_trip() {
+        let network = Network::new(16, 1);
+        let mut bytes = Vec::new();
+        network.write(&mut bytes).unwrap();
+        assert_eq!(Network::read(&bytes[..]).unwrap(), network);
+        assert!(Network::read(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Network::read(&b"OTNN\x09\x00\x00\x00\x10\x00\x00\x00"[..]).is_err());
+
+        let (priors, value) = network.evaluate(&BitBoard::initial());
+        assert_eq!(priors.len(), 4);
+        assert!((priors.iter().map(|&(_, p)| p).sum::<f32>() - 1.0).abs() < 1e-5);
+        assert!((-1.0..=1.0).contains(&value));
+    }
+
+    #[test]
+    fn training_fits_examples() {
+        // A position without symmetries, so that they all ask the same.
+        let board = [1 << 26, 1 << 20, 1 << 29]
+            .iter()
+            .fold(BitBoard::initial(), |board, &position| board.play(position));
+        let example = Example {
+            board,
+            policy: vec![(board.moves()[0], 1.0)],
+            value: 0.5,
+        };
+        let mut network = Network::new(16, 2);
+        let mut rng = StdRng::seed_from_u64(0);
+        let batch = vec![example.clone(); 8];
+        let first = network.train(&batch, 0.05, &mut rng);
+        let mut last = first;
+        for _ in 0..200 {
+            last = network.train(&batch, 0.05, &mut rng);
+        }
+        assert!(last.value < first.value / 10.0, "{:?} {:?}", first, last);
+        assert!(last.policy < first.policy / 2.0, "{:?} {:?}", first, last);
+    }
+}
@@ -0,0 +1,178 @@
+//! Improving a network by self-play: each generation plays games with the
+//! current network guiding `MCTS`, adds their positions to a window of the
+//! most recent ones, and trains the network on that window, teaching the
+//! policy the search's visits and the value the game results.
+
+use super::{Example, Loss, Network};
+use crate::othello::engine::{EngineKind, Limits};
+use crate::othello::selfplay::SelfPlay;
+use rand::prelude::*;
+use std::collections::VecDeque;
+
+/// Settings of a training run.
+#[derive(Clone, Debug)]
+pub struct Training {
+    pub generations: u32,
+    /// Self-play games per generation.
+    pub games: u32,
+    /// Search per move of the self-play games.
+    pub limits: Limits,
+    /// Games played at once.
+    pub threads: usize,
+    /// Most recent positions trained on.
+    pub window: usize,
+    /// Passes over the window after each generation.
+    pub epochs: u32,
+    pub batch: usize,
+    pub rate: f32,
+    /// Moves drawn in proportion to the visits at the start of each game,
+    /// see `SelfPlay`.
+    pub temperature_plies: usize,
+    pub seed: u64,
+}
+
+impl Default for Training {
+    fn default() -> Self {
+        Self {
+            generations: 10,
+            games: 100,
+            limits: Limits::iterations(200),
+            threads: 1,
+            window: 100_000,
+            epochs: 2,
+            batch: 64,
+            rate: 0.01,
+            temperature_plies: 20,
+            seed: 0,
+        }
+    }
+}
+
+/// Progress after each generation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GenerationReport {
+    pub generation: u32,
+    pub games: u32,
+    /// Games won by black and white, and draws.
+    pub wins: [u32; 3],
+    /// Positions in the window.
+    pub positions: usize,
+    /// Mean losses over the last epoch.
+    pub loss: Loss,
+}
+
+impl Training {
+    /// Trains `network`, writing it to `path` after every generation, where
+    /// the self-play games of the next one load it from. Stops at the first
+    /// error.
+    pub fn run<F>(&self, network: &mut Network, path: &str, mut report: F) -> Result<(), String>
+    where
+        F: FnMut(&GenerationReport),
+    {
+        let save = |network: &Network| {
+            network
+                .save(path)
+                .map_err(|err| format!("{}: {}", path, err))
+        };
+        save(network)?;
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut window: VecDeque<Example> = VecDeque::new();
+        for generation in 1..=self.generations {
+            let self_play = SelfPlay {
+                engine: EngineKind::Network(path.to_owned()),
+                limits: self.limits,
+                games: self.games,
+                threads: self.threads,
+                temperature_plies: self.temperature_plies,
+                seed: self.seed ^ generation as u64,
+                ..Default::default()
+            };
+            let mut error = None;
+            let mut wins = [0; 3];
+            self_play.run(|_, samples| match samples {
+                Ok(samples) => {
+                    if let Some(first) = samples.first() {
+                        let black = if first.board.player() == 0 {
+                            first.result
+                        } else {
+                            -first.result
+                        };
+                        wins[match black.signum() {
+                            1 => 0,
+                            -1 => 1,
+                            _ => 2,
+                        }] += 1;
+                    }
+                    window.extend(samples.iter().map(Example::from_sample));
+                }
+                Err(err) => {
+                    error.get_or_insert(err);
+                }
+            });
+            if let Some(err) = error {
+                return Err(err.to_string());
+            }
+            while window.len() > self.window {
+                window.pop_front();
+            }
+
+            let mut loss = Loss::default();
+            let mut order: Vec<usize> = (0..window.len()).collect();
+            for _ in 0..self.epochs {
+                order.shuffle(&mut rng);
+                let (mut total, mut batches) = (Loss::default(), 0);
+                for indices in order.chunks(self.batch.max(1)) {
+                    let batch: Vec<Example> = indices.iter().map(|&i| window[i].clone()).collect();
+                    let batch_loss = network.train(&batch, self.rate, &mut rng);
+                    total.value += batch_loss.value;
+                    total.policy += batch_loss.policy;
+                    batches += 1;
+                }
+                loss = Loss {
+                    value: total.value / batches.max(1) as f64,
+                    policy: total.policy / batches.max(1) as f64,
+                };
+            }
+            save(network)?;
+
+            report(&GenerationReport {
+                generation,
+                games: self.games,
+                wins,
+                positions: window.len(),
+                loss,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generations_train_and_save_the_network() {
+        let path =
+            std::env::temp_dir().join(format!("othello-training-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        let training = Training {
+            generations: 2,
+            games: 2,
+            limits: Limits::iterations(8),
+            epochs: 1,
+            ..Default::default()
+        };
+        let mut network = Network::new(8, 0);
+        let mut reports = Vec::new();
+        training
+            .run(&mut network, path, |report| reports.push(report.clone()))
+            .unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[1].wins.iter().sum::<u32>(), 2);
+        assert!(reports[1].positions > reports[0].positions);
+        assert_eq!(Network::load(path).unwrap(), network);
+        std::fs::remove_file(path).unwrap();
+    }
+}
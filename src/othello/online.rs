@@ -0,0 +1,379 @@
+//! Games on a server, played over WebSocket from the native app or the
+//! browser. The server (`src/bin/server.rs`) keeps rooms, each with a game,
+//! two seats, spectators and a clock per side, and checks every move.
+//!
+//! Messages are JSON objects with a `type` field, such as
+//! `{"type":"move","square":"f5"}`. A client first sends `join`; the server
+//! answers `joined` and then sends the whole `state` of the room whenever
+//! it changes.
+
+pub mod client;
+
+use super::game::{self, parse_square, square_name, Game};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use wasm_timer::Instant;
+
+/// Bumped whenever the messages change.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Default time per side.
+pub const DEFAULT_TIME: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Seat {
+    Black,
+    White,
+    Spectator,
+}
+
+impl Seat {
+    /// The player sitting here, if any.
+    pub fn player(self) -> Option<i32> {
+        match self {
+            Seat::Black => Some(0),
+            Seat::White => Some(1),
+            Seat::Spectator => None,
+        }
+    }
+
+    fn of_player(player: i32) -> Seat {
+        if player == 0 {
+            Seat::Black
+        } else {
+            Seat::White
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Enters `room`, which is created if needed. Without a `seat` the
+    /// client takes a free colour, or watches if both are taken.
+    Join {
+        version: u32,
+        room: String,
+        name: String,
+        seat: Option<Seat>,
+    },
+    Move {
+        square: String,
+    },
+    Resign,
+    /// Starts a new game with colours swapped, once the game is over.
+    Rematch,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Joined { room: String, seat: Seat },
+    State(RoomState),
+    Error { message: String },
+}
+
+/// Everything a client shows about a room.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RoomState {
+    pub black: Option<String>,
+    pub white: Option<String>,
+    pub spectators: Vec<String>,
+    /// Moves in `f5` notation, passes included as `pa`.
+    pub moves: Vec<String>,
+    /// Milliseconds left to black and white when the state was sent.
+    pub clocks: [u64; 2],
+    /// Whose clock is running: the side to move, once both seats are taken.
+    pub running: Option<Seat>,
+    /// Set when the game is over, such as `black wins 40-24`.
+    pub result: Option<String>,
+}
+
+impl RoomState {
+    /// The game so far; `None` if a move does not parse.
+    pub fn game(&self) -> Option<Game> {
+        let mut game = Game::default();
+        for name in self.moves.iter() {
+            game.play(parse_square(name)?).ok()?;
+        }
+
+        Some(game)
+    }
+}
+
+struct Occupant {
+    id: u64,
+    name: String,
+}
+
+/// A game with its players and clocks. Clients are identified by an id
+/// chosen by the server.
+pub struct Room {
+    game: Game,
+    players: [Option<Occupant>; 2],
+    spectators: Vec<Occupant>,
+    time: Duration,
+    clocks: [Duration; 2],
+    /// When the running clock was last brought up to date.
+    clock_updated: Option<Instant>,
+    result: Option<String>,
+}
+
+impl Room {
+    /// A room giving `time` to each side.
+    pub fn new(time: Duration) -> Self {
+        Self {
+            game: Game::default(),
+            players: [None, None],
+            spectators: Vec::new(),
+            time,
+            clocks: [time; 2],
+            clock_updated: None,
+            result: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.players.iter().all(Option::is_none) && self.spectators.is_empty()
+    }
+
+    /// Seats client `id` as asked and returns the seat taken.
+    pub fn join(
+        &mut self,
+        id: u64,
+        name: &str,
+        seat: Option<Seat>,
+        now: Instant,
+    ) -> Result<Seat, String> {
+        let seat = match seat {
+            Some(seat) => seat,
+            None => (0..2)
+                .find(|&player| self.players[player].is_none())
+                .map_or(Seat::Spectator, |player| Seat::of_player(player as i32)),
+        };
+        let occupant = Occupant {
+            id,
+            name: name.to_owned(),
+        };
+        self.update_clock(now);
+        match seat.player() {
+            Some(player) => {
+                let place = &mut self.players[player as usize];
+                if place.is_some() {
+                    return Err(format!("{} is taken", game::player_name(player)));
+                }
+                *place = Some(occupant);
+            }
+            None => self.spectators.push(occupant),
+        }
+        self.update_clock(now);
+
+        Ok(seat)
+    }
+
+    /// Frees whatever client `id` occupied. The game waits, with the clocks
+    /// stopped, until someone takes the seat again.
+    pub fn leave(&mut self, id: u64, now: Instant) {
+        self.update_clock(now);
+        for place in self.players.iter_mut() {
+            if place.as_ref().is_some_and(|occupant| occupant.id == id) {
+                *place = None;
+            }
+        }
+        self.spectators.retain(|occupant| occupant.id != id);
+        self.update_clock(now);
+    }
+
+    /// The colour client `id` plays, if any.
+    fn player_of(&self, id: u64) -> Option<i32> {
+        (0..2).find(|&player| {
+            self.players[player as usize]
+                .as_ref()
+                .is_some_and(|occupant| occupant.id == id)
+        })
+    }
+
+    /// Where client `id` sits; a rematch swaps the players' seats.
+    pub fn seat_of(&self, id: u64) -> Seat {
+        self.player_of(id).map_or(Seat::Spectator, Seat::of_player)
+    }
+
+    fn is_running(&self) -> bool {
+        self.players.iter().all(Option::is_some) && self.result.is_none()
+    }
+
+    /// Charges the time since the last update to the side to move, and
+    /// starts or stops the clock as the room now requires.
+    fn update_clock(&mut self, now: Instant) {
+        if let Some(since) = self.clock_updated {
+            let player = self.game.position().player() as usize;
+            self.clocks[player] = self.clocks[player].saturating_sub(now - since);
+            if self.clocks[player] == Duration::ZERO && self.result.is_none() {
+                let winner = game::player_name(1 - player as i32);
+                self.result = Some(format!("{} wins on time", winner));
+            }
+        }
+        self.clock_updated = if self.is_running() { Some(now) } else { None };
+    }
+
+    /// Plays `square` for client `id`, then any passes that follow.
+    pub fn play(&mut self, id: u64, square: &str, now: Instant) -> Result<(), String> {
+        let player = self.player_of(id).ok_or("you are not playing")?;
+        self.update_clock(now);
+        if !self.is_running() {
+            return Err(match &self.result {
+                Some(result) => format!("the game is over: {}", result),
+                None => "waiting for an opponent".to_owned(),
+            });
+        }
+        let position = parse_square(square).ok_or_else(|| format!("bad square: {}", square))?;
+        if self.game.position().player() != player {
+            return Err("not your turn".to_owned());
+        }
+        self.game.play(position)?;
+        while game::is_legal(&self.game.position(), 0) {
+            self.game.moves.push(0);
+        }
+        let board = self.game.position();
+        if board.is_game_ended() {
+            let (black, white) = board.count();
+            self.result = Some(match black.cmp(&white) {
+                std::cmp::Ordering::Greater => format!("black wins {}-{}", black, white),
+                std::cmp::Ordering::Less => format!("white wins {}-{}", black, white),
+                std::cmp::Ordering::Equal => format!("draw {}-{}", black, white),
+            });
+        }
+        self.update_clock(now);
+
+        Ok(())
+    }
+
+    pub fn resign(&mut self, id: u64, now: Instant) -> Result<(), String> {
+        let player = self.player_of(id).ok_or("you are not playing")?;
+        self.update_clock(now);
+        if self.result.is_some() {
+            return Err("the game is over".to_owned());
+        }
+        self.result = Some(format!("{} resigned", game::player_name(player)));
+        self.update_clock(now);
+
+        Ok(())
+    }
+
+    /// Starts a new game with the players' colours swapped.
+    pub fn rematch(&mut self, id: u64, now: Instant) -> Result<(), String> {
+        self.player_of(id).ok_or("you are not playing")?;
+        if self.result.is_none() {
+            return Err("the game is not over".to_owned());
+        }
+        self.players.swap(0, 1);
+        self.game = Game::default();
+        self.clocks = [self.time; 2];
+        self.result = None;
+        self.update_clock(now);
+
+        Ok(())
+    }
+
+    /// Brings the clocks up to date; returns true if a flag fell.
+    pub fn tick(&mut self, now: Instant) -> bool {
+        let finished = self.result.is_some();
+        self.update_clock(now);
+        !finished && self.result.is_some()
+    }
+
+    pub fn state(&self, now: Instant) -> RoomState {
+        let mut clocks = self.clocks;
+        if let Some(since) = self.clock_updated {
+            let player = self.game.position().player() as usize;
+            clocks[player] = clocks[player].saturating_sub(now - since);
+        }
+        let name = |place: &Option<Occupant>| place.as_ref().map(|o| o.name.clone());
+
+        RoomState {
+            black: name(&self.players[0]),
+            white: name(&self.players[1]),
+            spectators: self.spectators.iter().map(|o| o.name.clone()).collect(),
+            moves: self.game.moves.iter().map(|&m| square_name(m)).collect(),
+            clocks: [clocks[0].as_millis() as u64, clocks[1].as_millis() as u64],
+            running: self
+                .clock_updated
+                .map(|_| Seat::of_player(self.game.position().player())),
+            result: self.result.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_tagged_json() {
+        let message = ClientMessage::Move {
+            square: "f5".to_owned(),
+        };
+        let json = serde_json::to_string(&message).unwrap();
+        assert_eq!(json, r#"{"type":"move","square":"f5"}"#);
+        let join: ClientMessage = serde_json::from_str(
+            r#"{"type":"join","version":1,"room":"lobby","name":"ann","seat":"white"}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            join,
+            ClientMessage::Join {
+                seat: Some(Seat::White),
+                ..
+            }
+        ));
+        let state = ServerMessage::State(RoomState::default());
+        let json = serde_json::to_string(&state).unwrap();
+        assert_eq!(serde_json::from_str::<ServerMessage>(&json).unwrap(), state);
+    }
+
+    #[test]
+    fn rooms_check_moves_and_run_clocks() {
+        let start = Instant::now();
+        let second = |s: u64| start + Duration::from_secs(s);
+        let mut room = Room::new(Duration::from_secs(60));
+        assert_eq!(room.join(1, "ann", None, start), Ok(Seat::Black));
+        assert!(room.play(1, "f5", start).is_err(), "no opponent yet");
+        assert_eq!(room.join(2, "bob", None, second(10)), Ok(Seat::White));
+        assert_eq!(room.join(3, "cy", None, second(10)), Ok(Seat::Spectator));
+        assert!(room.join(3, "cy", Some(Seat::Black), second(10)).is_err());
+
+        assert!(room.play(2, "f5", second(10)).is_err(), "not white's turn");
+        assert!(room.play(1, "a1", second(10)).is_err(), "illegal");
+        assert!(room.play(3, "f5", second(10)).is_err(), "spectator");
+        room.play(1, "f5", second(15)).unwrap();
+        let state = room.state(second(20));
+        assert_eq!(state.moves, vec!["f5"]);
+        // The clock did not run while black waited for an opponent.
+        assert_eq!(state.clocks, [55_000, 55_000]);
+        assert_eq!(state.running, Some(Seat::White));
+
+        // White leaves: the clock stops until the seat is taken again.
+        room.leave(2, second(20));
+        assert_eq!(room.state(second(100)).clocks, [55_000, 55_000]);
+        room.join(4, "bob", Some(Seat::White), second(100)).unwrap();
+        assert!(!room.tick(second(150)));
+        assert!(room.tick(second(160)));
+        let state = room.state(second(170));
+        assert_eq!(state.result.as_deref(), Some("black wins on time"));
+        assert_eq!(state.running, None);
+
+        room.rematch(1, second(170)).unwrap();
+        let state = room.state(second(170));
+        assert_eq!(state.black.as_deref(), Some("bob"));
+        assert!(state.moves.is_empty());
+        assert_eq!(state.game(), Some(Game::default()));
+        assert_eq!(room.seat_of(4), Seat::Black);
+        assert_eq!(room.seat_of(1), Seat::White);
+        assert_eq!(room.seat_of(3), Seat::Spectator);
+        assert!(room.play(1, "f5", second(171)).is_err(), "not white's turn");
+        room.play(4, "f5", second(171)).unwrap();
+        room.play(1, "d6", second(172)).unwrap();
+        assert_eq!(room.state(second(172)).moves, vec!["f5", "d6"]);
+    }
+}
@@ -0,0 +1,294 @@
+//! The client side of online games. The connection runs on a thread with
+//! tungstenite natively and on the browser's `WebSocket` in wasm; both are
+//! polled from the UI.
+
+use super::{ClientMessage, RoomState, Seat, ServerMessage, PROTOCOL_VERSION};
+use wasm_timer::Instant;
+
+enum Event {
+    Message(ServerMessage),
+    Closed(String),
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod connection {
+    use super::{ClientMessage, Event};
+    use std::io::ErrorKind;
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::thread;
+    use std::time::Duration;
+    use tungstenite::stream::MaybeTlsStream;
+    use tungstenite::Message;
+
+    pub struct Connection {
+        outgoing: Sender<ClientMessage>,
+        incoming: Receiver<Event>,
+    }
+
+    impl Connection {
+        pub fn open(url: &str) -> Self {
+            let (outgoing, outgoing_receiver) = mpsc::channel();
+            let (incoming_sender, incoming) = mpsc::channel();
+            let url = url.to_owned();
+            thread::spawn(move || {
+                let reason = run(&url, outgoing_receiver, &incoming_sender)
+                    .err()
+                    .unwrap_or_else(|| "connection closed".to_owned());
+                let _ = incoming_sender.send(Event::Closed(reason));
+            });
+
+            Self { outgoing, incoming }
+        }
+
+        pub fn send(&mut self, message: ClientMessage) {
+            let _ = self.outgoing.send(message);
+        }
+
+        pub fn receive(&mut self) -> Option<Event> {
+            self.incoming.try_recv().ok()
+        }
+    }
+
+    /// Passes messages both ways until the socket closes or the
+    /// `Connection` is dropped.
+    fn run(
+        url: &str,
+        outgoing: Receiver<ClientMessage>,
+        incoming: &Sender<Event>,
+    ) -> Result<(), String> {
+        let (mut socket, _) = tungstenite::connect(url).map_err(|err| err.to_string())?;
+        if let MaybeTlsStream::Plain(stream) = socket.get_ref() {
+            stream
+                .set_read_timeout(Some(Duration::from_millis(50)))
+                .map_err(|err| err.to_string())?;
+        }
+        loop {
+            loop {
+                match outgoing.try_recv() {
+                    Ok(message) => {
+                        let text = serde_json::to_string(&message).unwrap();
+                        socket
+                            .send(Message::Text(text))
+                            .map_err(|err| err.to_string())?;
+                    }
+                    Err(mpsc::TryRecvError::Empty) => break,
+                    Err(mpsc::TryRecvError::Disconnected) => {
+                        let _ = socket.close(None);
+                        return Ok(());
+                    }
+                }
+            }
+            match socket.read() {
+                Ok(Message::Text(text)) => {
+                    let message = serde_json::from_str(&text).map_err(|err| err.to_string())?;
+                    if incoming.send(Event::Message(message)).is_err() {
+                        return Ok(());
+                    }
+                }
+                Ok(Message::Close(_)) => return Ok(()),
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(err))
+                    if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(err) => return Err(err.to_string()),
+            }
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod connection {
+    use super::{ClientMessage, Event};
+    use eframe::wasm_bindgen::{closure::Closure, JsCast};
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+    use web_sys::{CloseEvent, MessageEvent, WebSocket};
+
+    pub struct Connection {
+        socket: Option<WebSocket>,
+        /// Sent once the socket opens.
+        pending: Rc<RefCell<Vec<String>>>,
+        events: Rc<RefCell<VecDeque<Event>>>,
+        // Kept alive for as long as the socket may call them.
+        _on_open: Closure<dyn FnMut()>,
+        _on_message: Closure<dyn FnMut(MessageEvent)>,
+        _on_close: Closure<dyn FnMut(CloseEvent)>,
+    }
+
+    impl Connection {
+        pub fn open(url: &str) -> Self {
+            let pending = Rc::new(RefCell::new(Vec::<String>::new()));
+            let events = Rc::new(RefCell::new(VecDeque::new()));
+            let socket = WebSocket::new(url).ok();
+
+            let on_open = {
+                let pending = pending.clone();
+                let socket = socket.clone();
+                Closure::wrap(Box::new(move || {
+                    if let Some(socket) = socket.as_ref() {
+                        for text in pending.borrow_mut().drain(..) {
+                            let _ = socket.send_with_str(&text);
+                        }
+                    }
+                }) as Box<dyn FnMut()>)
+            };
+            let on_message = {
+                let events = events.clone();
+                Closure::wrap(Box::new(move |event: MessageEvent| {
+                    let message = event
+                        .data()
+                        .as_string()
+                        .and_then(|text| serde_json::from_str(&text).ok());
+                    if let Some(message) = message {
+                        events.borrow_mut().push_back(Event::Message(message));
+                    }
+                }) as Box<dyn FnMut(MessageEvent)>)
+            };
+            let on_close = {
+                let events = events.clone();
+                Closure::wrap(Box::new(move |event: CloseEvent| {
+                    let reason = match event.reason() {
+                        reason if reason.is_empty() => format!("closed ({})", event.code()),
+                        reason => reason,
+                    };
+                    events.borrow_mut().push_back(Event::Closed(reason));
+                }) as Box<dyn FnMut(CloseEvent)>)
+            };
+            match socket.as_ref() {
+                Some(socket) => {
+                    socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+                    socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+                    socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+                }
+                None => events
+                    .borrow_mut()
+                    .push_back(Event::Closed(format!("bad url: {}", url))),
+            }
+
+            Self {
+                socket,
+                pending,
+                events,
+                _on_open: on_open,
+                _on_message: on_message,
+                _on_close: on_close,
+            }
+        }
+
+        pub fn send(&mut self, message: ClientMessage) {
+            let text = serde_json::to_string(&message).unwrap();
+            match self.socket.as_ref() {
+                Some(socket) if socket.ready_state() == WebSocket::OPEN => {
+                    let _ = socket.send_with_str(&text);
+                }
+                _ => self.pending.borrow_mut().push(text),
+            }
+        }
+
+        pub fn receive(&mut self) -> Option<Event> {
+            self.events.borrow_mut().pop_front()
+        }
+    }
+
+    impl Drop for Connection {
+        fn drop(&mut self) {
+            if let Some(socket) = self.socket.as_ref() {
+                socket.set_onopen(None);
+                socket.set_onmessage(None);
+                socket.set_onclose(None);
+                let _ = socket.close();
+            }
+        }
+    }
+}
+
+/// A client in one room of a server.
+pub struct OnlineClient {
+    connection: connection::Connection,
+    pub room: String,
+    /// Where the server seated us, once it answered.
+    pub seat: Option<Seat>,
+    pub state: Option<RoomState>,
+    /// When `state` arrived, to run the clocks in between.
+    received: Instant,
+    /// The last error from the server, or why the connection closed.
+    pub error: Option<String>,
+    pub closed: bool,
+}
+
+impl OnlineClient {
+    /// Connects to `url`, such as `ws://localhost:9000`, and joins `room`.
+    pub fn join(url: &str, room: &str, name: &str, seat: Option<Seat>) -> Self {
+        let mut connection = connection::Connection::open(url);
+        connection.send(ClientMessage::Join {
+            version: PROTOCOL_VERSION,
+            room: room.to_owned(),
+            name: name.to_owned(),
+            seat,
+        });
+
+        Self {
+            connection,
+            room: room.to_owned(),
+            seat: None,
+            state: None,
+            received: Instant::now(),
+            error: None,
+            closed: false,
+        }
+    }
+
+    /// Handles what the server sent; returns true if the room changed.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        while let Some(event) = self.connection.receive() {
+            match event {
+                Event::Message(ServerMessage::Joined { room, seat }) => {
+                    self.room = room;
+                    self.seat = Some(seat);
+                    self.error = None;
+                }
+                Event::Message(ServerMessage::State(state)) => {
+                    self.state = Some(state);
+                    self.received = Instant::now();
+                    changed = true;
+                }
+                Event::Message(ServerMessage::Error { message }) => self.error = Some(message),
+                Event::Closed(reason) => {
+                    self.error = Some(reason);
+                    self.closed = true;
+                }
+            }
+        }
+
+        changed
+    }
+
+    pub fn send(&mut self, message: ClientMessage) {
+        self.connection.send(message);
+    }
+
+    /// Milliseconds left to black and white now, counting down the running
+    /// clock since the last state.
+    pub fn clocks(&self) -> [u64; 2] {
+        let state = match self.state.as_ref() {
+            Some(state) => state,
+            None => return [0; 2],
+        };
+        let mut clocks = state.clocks;
+        if let Some(player) = state.running.and_then(Seat::player) {
+            let elapsed = self.received.elapsed().as_millis() as u64;
+            clocks[player as usize] = clocks[player as usize].saturating_sub(elapsed);
+        }
+
+        clocks
+    }
+
+    /// Whether it is our turn to move.
+    pub fn is_my_turn(&self) -> bool {
+        match (self.seat.and_then(Seat::player), self.state.as_ref()) {
+            (Some(player), Some(state)) => state.running == Some(Seat::of_player(player)),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! What the GUI draws over the board besides the disks.
+
+use super::moai::BitBoard;
+
+/// Which overlays are shown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "persistence", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "persistence", serde(default))]
+pub struct Overlays {
+    /// Legal moves of the side to move.
+    pub legal_moves: bool,
+    pub last_move: bool,
+    /// Disks flipped by the last move.
+    pub flips: bool,
+}
+
+impl Default for Overlays {
+    fn default() -> Self {
+        Self {
+            legal_moves: true,
+            last_move: true,
+            flips: true,
+        }
+    }
+}
+
+/// A move and the disks it flipped, one bit each.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LastMove {
+    pub position: u64,
+    pub flipped: u64,
+}
+
+impl LastMove {
+    /// The move leading from `before` to `after`, if a single legal move of
+    /// either side does. Whoever is to move in `before` is not trusted, as
+    /// the GUI may have applied a pass in between.
+    pub fn between(before: &BitBoard, after: &BitBoard) -> Option<Self> {
+        let (black, white) = before.bits();
+        let (next_black, next_white) = after.bits();
+        let placed = (next_black | next_white) & !(black | white);
+        if placed.count_ones() != 1 {
+            return None;
+        }
+
+        [before.player(), 1 - before.player()]
+            .iter()
+            .map(|&player| BitBoard::from_bits(black, white, player))
+            .find(|board| {
+                board.legal_bits() & placed != 0 && board.play(placed).bits() == after.bits()
+            })
+            .map(|board| LastMove {
+                position: placed,
+                flipped: board.flips(placed),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::othello::game::parse_square;
+
+    #[test]
+    fn last_move_is_found_from_the_positions() {
+        let initial = BitBoard::initial();
+        let f5 = parse_square("f5").unwrap();
+        let after = initial.play(f5);
+        let last = LastMove::between(&initial, &after).unwrap();
+        assert_eq!(last.position, f5);
+        assert_eq!(last.flipped, parse_square("e5").unwrap());
+
+        // The side to move of `before` does not matter.
+        let (black, white) = initial.bits();
+        let passed = BitBoard::from_bits(black, white, 1);
+        assert_eq!(LastMove::between(&passed, &after), Some(last));
+
+        // Neither a jump of several moves nor going back is a move.
+        let later = after.play(after.moves()[0]);
+        assert_eq!(LastMove::between(&initial, &later), None);
+        assert_eq!(LastMove::between(&after, &initial), None);
+    }
+}
@@ -0,0 +1,304 @@
+//! A pattern evaluation: the disk difference a position is expected to end
+//! with, as a sum of weights looked up for the contents of lines and corner
+//! regions of the board. Each pattern is read in all of its symmetric
+//! placements, from the side to move's point of view, and every game phase
+//! has its own weights. `train` fits them to recorded games.
+//!
+//! Weights are stored in a binary file: the magic bytes `OTEW`, the format
+//! version, `u16`, the number of phases, `u16`, and the number of weights
+//! per phase, `u32`, then the weights as `f32`, phase after phase. All
+//! numbers are little-endian.
+
+use super::moai::BitBoard;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+pub const MAGIC: &[u8; 4] = b"OTEW";
+
+/// Bumped whenever the file or the patterns change.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// Phases are ranges of 10 empty squares: 1 to 10, 11 to 20 and so on.
+pub const PHASES: usize = 6;
+
+/// Squares of each pattern as (column, row), in one of its placements.
+const PATTERNS: [&[(u8, u8)]; 5] = [
+    // An edge with the two X squares.
+    &[
+        (0, 0),
+        (1, 0),
+        (2, 0),
+        (3, 0),
+        (4, 0),
+        (5, 0),
+        (6, 0),
+        (7, 0),
+        (1, 1),
+        (6, 1),
+    ],
+    // A 3x3 corner.
+    &[
+        (0, 0),
+        (1, 0),
+        (2, 0),
+        (0, 1),
+        (1, 1),
+        (2, 1),
+        (0, 2),
+        (1, 2),
+        (2, 2),
+    ],
+    // The second row.
+    &[
+        (0, 1),
+        (1, 1),
+        (2, 1),
+        (3, 1),
+        (4, 1),
+        (5, 1),
+        (6, 1),
+        (7, 1),
+    ],
+    // A main diagonal.
+    &[
+        (0, 0),
+        (1, 1),
+        (2, 2),
+        (3, 3),
+        (4, 4),
+        (5, 5),
+        (6, 6),
+        (7, 7),
+    ],
+    // A diagonal of seven squares.
+    &[(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 6), (6, 7)],
+];
+
+/// The phase of a position with `empties` empty squares.
+pub fn phase(empties: u32) -> usize {
+    (empties.saturating_sub(1) as usize / 10).min(PHASES - 1)
+}
+
+/// Empty squares of the positions in `phase`, such as `11-20`.
+pub fn phase_name(phase: usize) -> String {
+    format!("{}-{}", phase * 10 + 1, phase * 10 + 10)
+}
+
+/// The 8 symmetries of the board.
+fn transform((x, y): (u8, u8), symmetry: usize) -> (u8, u8) {
+    let (x, y) = if symmetry & 4 != 0 { (y, x) } else { (x, y) };
+    let x = if symmetry & 1 != 0 { 7 - x } else { x };
+    let y = if symmetry & 2 != 0 { 7 - y } else { y };
+    (x, y)
+}
+
+fn square((x, y): (u8, u8)) -> u64 {
+    1 << (63 - (y as u32 * 8 + x as u32))
+}
+
+/// One placement of a pattern: its squares and where its weights start
+/// within a phase.
+#[derive(Clone, Debug)]
+struct Placement {
+    squares: Vec<u64>,
+    offset: usize,
+}
+
+/// Every placement of every pattern, and the number of weights per phase,
+/// the last of which is a constant term.
+fn placements() -> (Vec<Placement>, usize) {
+    let mut placements = Vec::new();
+    let mut offset = 0;
+    for pattern in PATTERNS.iter() {
+        let mut seen: Vec<u64> = Vec::new();
+        for symmetry in 0..8 {
+            let squares: Vec<u64> = pattern
+                .iter()
+                .map(|&xy| square(transform(xy, symmetry)))
+                .collect();
+            // Symmetries mapping the pattern onto itself add nothing.
+            let mask = squares.iter().fold(0, |mask, square| mask | square);
+            if !seen.contains(&mask) {
+                seen.push(mask);
+                placements.push(Placement { squares, offset });
+            }
+        }
+        offset += 3usize.pow(pattern.len() as u32);
+    }
+
+    (placements, offset + 1)
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Pattern weights for every phase.
+#[derive(Clone, Debug)]
+pub struct Evaluator {
+    placements: Vec<Placement>,
+    per_phase: usize,
+    weights: Vec<f32>,
+}
+
+impl Default for Evaluator {
+    /// All weights zero, to be trained.
+    fn default() -> Self {
+        let (placements, per_phase) = placements();
+        Self {
+            placements,
+            per_phase,
+            weights: vec![0.0; per_phase * PHASES],
+        }
+    }
+}
+
+impl Evaluator {
+    pub fn read<R: Read>(mut input: R) -> io::Result<Self> {
+        let mut header = [0; 12];
+        input.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(invalid("not a weights file".to_owned()));
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version != FORMAT_VERSION {
+            return Err(invalid(format!("unsupported format version {}", version)));
+        }
+        let mut evaluator = Self::default();
+        let phases = u16::from_le_bytes([header[6], header[7]]) as usize;
+        let per_phase = u32::from_le_bytes(header[8..].try_into().unwrap()) as usize;
+        if phases != PHASES || per_phase != evaluator.per_phase {
+            return Err(invalid(format!(
+                "{} phases of {} weights, expected {} of {}",
+                phases, per_phase, PHASES, evaluator.per_phase
+            )));
+        }
+        let mut bytes = vec![0; evaluator.weights.len() * 4];
+        input.read_exact(&mut bytes)?;
+        for (weight, bytes) in evaluator.weights.iter_mut().zip(bytes.chunks_exact(4)) {
+            *weight = f32::from_le_bytes(bytes.try_into().unwrap());
+        }
+
+        Ok(evaluator)
+    }
+
+    pub fn write<W: Write>(&self, mut out: W) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        out.write_all(&(PHASES as u16).to_le_bytes())?;
+        out.write_all(&(self.per_phase as u32).to_le_bytes())?;
+        let mut bytes = Vec::with_capacity(self.weights.len() * 4);
+        for weight in self.weights.iter() {
+            bytes.extend_from_slice(&weight.to_le_bytes());
+        }
+
+        out.write_all(&bytes)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write(&mut out)?;
+        out.flush()
+    }
+
+    /// The weights that add up to the value of the position with `player`
+    /// to move, one per pattern placement and the constant term.
+    pub fn features(&self, player: u64, opponent: u64) -> Vec<usize> {
+        let empties = (!(player | opponent)).count_ones();
+        let base = phase(empties) * self.per_phase;
+        let mut features: Vec<usize> = self
+            .placements
+            .iter()
+            .map(|placement| base + placement.offset + index(placement, player, opponent))
+            .collect();
+        features.push(base + self.per_phase - 1);
+        features
+    }
+
+    /// Expected final disk difference for `player`.
+    pub fn evaluate_bits(&self, player: u64, opponent: u64) -> f32 {
+        let empties = (!(player | opponent)).count_ones();
+        let base = phase(empties) * self.per_phase;
+        let constant = self.weights[base + self.per_phase - 1];
+        self.placements.iter().fold(constant, |sum, placement| {
+            sum + self.weights[base + placement.offset + index(placement, player, opponent)]
+        })
+    }
+
+    /// Expected final disk difference for the side to move.
+    pub fn evaluate(&self, board: &BitBoard) -> f32 {
+        let (player, opponent) = board.curr_board();
+        self.evaluate_bits(player, opponent)
+    }
+
+    /// Adds `delta` to each of `features`.
+    pub fn update(&mut self, features: &[usize], delta: f32) {
+        for &feature in features.iter() {
+            self.weights[feature] += delta;
+        }
+    }
+}
+
+/// The contents of a placement as a number in base 3: 0 for an empty
+/// square, 1 for the player's disk and 2 for the opponent's.
+fn index(placement: &Placement, player: u64, opponent: u64) -> usize {
+    placement.squares.iter().fold(0, |index, &square| {
+        index * 3
+            + if player & square != 0 {
+                1
+            } else if opponent & square != 0 {
+                2
+            } else {
+                0
+            }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placements_cover_symmetries() {
+        let (placements, per_phase) = placements();
+        // 4 edges, 4 corners, 4 second rows, 2 diagonals and 4 short ones.
+        assert_eq!(placements.len(), 18);
+        assert_eq!(per_phase, 59049 + 19683 + 6561 * 2 + 2187 + 1);
+        assert_eq!(phase(60), 5);
+        assert_eq!(phase(10), 0);
+        assert_eq!(phase(11), 1);
+    }
+
+    #[test]
+    fn weights_round_trip() {
+        let mut evaluator = Evaluator::default();
+        let board = BitBoard::initial().play(1 << 26);
+        let (player, opponent) = board.curr_board();
+        let features = evaluator.features(player, opponent);
+        evaluator.update(&features, 0.5);
+        let value = evaluator.evaluate(&board);
+        // Placements with the same contents, such as empty edges, share a
+        // weight.
+        let expected: f32 = features
+            .iter()
+            .map(|f| 0.5 * features.iter().filter(|&g| g == f).count() as f32)
+            .sum();
+        assert_eq!(value, expected);
+        // Seen by the other side, the placements holding disks read other
+        // weights.
+        assert!(evaluator.evaluate_bits(opponent, player) < value);
+
+        let mut bytes = Vec::new();
+        evaluator.write(&mut bytes).unwrap();
+        let read = Evaluator::read(&bytes[..]).unwrap();
+        assert_eq!(read.evaluate(&board), value);
+        assert!(Evaluator::read(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Evaluator::read(&b"OTEW\x09\x00"[..]).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! Searching during the opponent's turn.
+//!
+//! A `Ponderer` owns an engine set to the position the opponent has to move
+//! in and keeps it thinking. Natively this happens on a background thread;
+//! on the web the search advances a little on every call to `step`.
+//! `finish` hands the engine back, and engines that keep their search
+//! between moves (such as `MctsEngine`) then reuse whatever they found for
+//! the move that was actually played.
+
+use super::engine::{Engine, Limits, SearchInfo};
+use super::moai::BitBoard;
+
+/// Milliseconds per call to `Engine::think`, which bounds how long `finish`
+/// waits natively.
+#[cfg(not(target_arch = "wasm32"))]
+const SLICE: u128 = 100;
+
+/// Pondering gives up after this many milliseconds or simulations to bound
+/// memory use. A simulation adds at most one node to an MCTS tree.
+const MAX_TIME: u128 = 60_000;
+const MAX_ITERATIONS: i32 = 500_000;
+
+/// Searches for `time` milliseconds, within what is left of the budget,
+/// and returns the simulations run, or `None` if the engine has nothing to
+/// gain from pondering: it is out of budget or does not use its time.
+fn ponder(
+    engine: &mut dyn Engine,
+    time: u128,
+    start: wasm_timer::Instant,
+    iterations: i32,
+) -> Option<i32> {
+    if start.elapsed().as_millis() >= MAX_TIME || iterations >= MAX_ITERATIONS {
+        return None;
+    }
+    let limits = Limits {
+        time: Some(time),
+        iterations: Some(MAX_ITERATIONS - iterations),
+    };
+    let slice = wasm_timer::Instant::now();
+    let mut info = SearchInfo::default();
+    let _ = engine.think(limits, &mut |i| info = i.clone());
+    // Engines that do not use their time have nothing to gain.
+    if slice.elapsed().as_millis() < time / 2 {
+        return None;
+    }
+
+    Some(info.iterations)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Ponderer {
+    stop: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    engine_stop: super::engine::StopHandle,
+    handle: Option<std::thread::JoinHandle<Box<dyn Engine>>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Ponderer {
+    pub fn start(mut engine: Box<dyn Engine>, state: BitBoard) -> Self {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let engine_stop = engine.stop_handle();
+        let flag = stop.clone();
+        engine.set_position(state);
+        let handle = std::thread::spawn(move || {
+            let start = wasm_timer::Instant::now();
+            let mut iterations = 0;
+            while !flag.load(Ordering::Relaxed) {
+                match ponder(engine.as_mut(), SLICE, start, iterations) {
+                    Some(n) => iterations += n,
+                    None => break,
+                }
+            }
+            engine
+        });
+
+        Self {
+            stop,
+            engine_stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// Does nothing: the search runs on its own thread.
+    pub fn step(&mut self, _time: u128) {}
+
+    /// Stops the search and returns the engine.
+    pub fn finish(mut self) -> Box<dyn Engine> {
+        self.stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        self.engine_stop.stop();
+        self.handle.take().unwrap().join().unwrap()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for Ponderer {
+    fn drop(&mut self) {
+        self.stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        self.engine_stop.stop();
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub struct Ponderer {
+    engine: Box<dyn Engine>,
+    start: wasm_timer::Instant,
+    iterations: i32,
+    done: bool,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Ponderer {
+    pub fn start(mut engine: Box<dyn Engine>, state: BitBoard) -> Self {
+        engine.set_position(state);
+        Self {
+            engine,
+            start: wasm_timer::Instant::now(),
+            iterations: 0,
+            done: false,
+        }
+    }
+
+    /// Searches for about `time` milliseconds. Call it once per frame.
+    pub fn step(&mut self, time: u128) {
+        if self.done {
+            return;
+        }
+        match ponder(self.engine.as_mut(), time, self.start, self.iterations) {
+            Some(n) => self.iterations += n,
+            None => self.done = true,
+        }
+    }
+
+    /// Returns the engine.
+    pub fn finish(self) -> Box<dyn Engine> {
+        self.engine
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::othello::engine::EngineKind;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn finishing_stops_the_search_and_returns_the_engine() {
+        let board = BitBoard::initial();
+        let state = board.play(board.moves()[0]);
+        let ponderer = Ponderer::start(EngineKind::parse("mcts").unwrap().create(), state);
+        std::thread::sleep(Duration::from_millis(200));
+        let start = Instant::now();
+        let mut engine = ponderer.finish();
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        // The engine searches again after a stop.
+        engine.set_position(state);
+        let mut last = SearchInfo::default();
+        engine
+            .think(Limits::iterations(100), &mut |i| last = i.clone())
+            .unwrap();
+        assert_eq!(last.iterations, 100);
+
+        let ponderer = Ponderer::start(EngineKind::Greedy.create(), state);
+        assert_eq!(ponderer.finish().name(), "greedy");
+    }
+}
@@ -0,0 +1,363 @@
+//! Self-play games recorded position by position, to train evaluations and
+//! to study how the engine plays.
+//!
+//! Samples are stored in a compact binary file that can be written and read
+//! as a stream: a header of the magic bytes `OTSP`, the format version and
+//! two reserved bytes, then one record per position. All numbers are
+//! little-endian. A record is
+//!
+//! * black and white disks, `u64` each, and the side to move, `u8`
+//! * the move played, `u8`: the bit index of the square, 64 for a pass
+//! * the search value for the side to move, `f32` from -1 to 1
+//! * the final disk difference for the side to move, `i8`
+//! * the number of root moves searched, `u8`, then for each the bit index
+//!   of its square, `u8`, and its visits, `u32`
+
+use super::engine::{Engine, EngineError, EngineKind, Limits, SearchInfo};
+use super::moai::BitBoard;
+use rand::distributions::WeightedIndex;
+use rand::prelude::*;
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc;
+
+pub const MAGIC: &[u8; 4] = b"OTSP";
+
+/// Bumped whenever the records change.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// One position of a self-play game.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    pub board: BitBoard,
+    /// The move played, 0 for a pass.
+    pub played: u64,
+    /// Search value for the side to move, from -1 to 1.
+    pub value: f32,
+    /// Final disk difference for the side to move, see
+    /// `BitBoard::final_score`.
+    pub result: i8,
+    /// Visits of each root move.
+    pub visits: Vec<(u64, u32)>,
+}
+
+fn square_index(position: u64) -> u8 {
+    if position == 0 {
+        64
+    } else {
+        position.trailing_zeros() as u8
+    }
+}
+
+fn square_of(index: u8) -> io::Result<u64> {
+    match index {
+        0..=63 => Ok(1 << index),
+        64 => Ok(0),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "bad square")),
+    }
+}
+
+/// Writes samples after the header.
+pub struct SampleWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> SampleWriter<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        out.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        out.write_all(&[0, 0])?;
+
+        Ok(Self { out })
+    }
+
+    pub fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        let (black, white) = sample.board.bits();
+        let mut record = Vec::with_capacity(24 + sample.visits.len() * 5);
+        record.extend_from_slice(&black.to_le_bytes());
+        record.extend_from_slice(&white.to_le_bytes());
+        record.push(sample.board.player() as u8);
+        record.push(square_index(sample.played));
+        record.extend_from_slice(&sample.value.to_le_bytes());
+        record.push(sample.result as u8);
+        record.push(sample.visits.len().min(255) as u8);
+        for &(position, visits) in sample.visits.iter().take(255) {
+            record.push(square_index(position));
+            record.extend_from_slice(&visits.to_le_bytes());
+        }
+
+        self.out.write_all(&record)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// Reads samples one at a time, checking the header first.
+pub struct SampleReader<R: Read> {
+    input: R,
+}
+
+impl<R: Read> SampleReader<R> {
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut header = [0; 8];
+        input.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a self-play file",
+            ));
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version != FORMAT_VERSION {
+            let message = format!("unsupported format version {}", version);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+
+        Ok(Self { input })
+    }
+
+    /// The next sample, or `None` at the end of the file.
+    fn read(&mut self) -> io::Result<Option<Sample>> {
+        let mut fixed = [0; 24];
+        // A clean end of file is allowed only between records.
+        let mut filled = 0;
+        while filled < fixed.len() {
+            match self.input.read(&mut fixed[filled..])? {
+                0 if filled == 0 => return Ok(None),
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => filled += n,
+            }
+        }
+        let u64_at = |i: usize| u64::from_le_bytes(fixed[i..i + 8].try_into().unwrap());
+        let player = fixed[16] as i32;
+        if player > 1 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bad player"));
+        }
+        let board = BitBoard::from_bits(u64_at(0), u64_at(8), player);
+        let played = square_of(fixed[17])?;
+        let value = f32::from_le_bytes(fixed[18..22].try_into().unwrap());
+        let result = fixed[22] as i8;
+        let mut visits = Vec::with_capacity(fixed[23] as usize);
+        for _ in 0..fixed[23] {
+            let mut entry = [0; 5];
+            self.input.read_exact(&mut entry)?;
+            let count = u32::from_le_bytes(entry[1..].try_into().unwrap());
+            visits.push((square_of(entry[0])?, count));
+        }
+
+        Ok(Some(Sample {
+            board,
+            played,
+            value,
+            result,
+            visits,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for SampleReader<R> {
+    type Item = io::Result<Sample>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
+
+/// Settings of a self-play run.
+#[derive(Clone, Debug)]
+pub struct SelfPlay {
+    pub engine: EngineKind,
+    pub limits: Limits,
+    pub games: u32,
+    pub threads: usize,
+    /// Game `i` starts after opening `i` modulo their number, or from the
+    /// initial position if there are none. Opening moves are not recorded.
+    pub openings: Vec<Vec<u64>>,
+    /// Uniformly random moves played after the opening, not recorded.
+    pub random_plies: usize,
+    /// In the first `temperature_plies` recorded plies, moves are drawn
+    /// with a probability proportional to `visits^(1 / temperature)`
+    /// instead of taking the engine's choice. 0 turns this off.
+    pub temperature: f64,
+    pub temperature_plies: usize,
+    /// Makes the random choices, though not the searches, repeatable.
+    pub seed: u64,
+}
+
+impl Default for SelfPlay {
+    fn default() -> Self {
+        Self {
+            engine: EngineKind::Mcts(Default::default()),
+            limits: Limits::iterations(1000),
+            games: 100,
+            threads: 1,
+            openings: Vec::new(),
+            random_plies: 0,
+            temperature: 1.0,
+            temperature_plies: 10,
+            seed: 0,
+        }
+    }
+}
+
+impl SelfPlay {
+    /// Plays the games on `threads` threads and calls `report` with the
+    /// number and samples of each game as it ends.
+    pub fn run<F>(&self, mut report: F)
+    where
+        F: FnMut(u32, Result<Vec<Sample>, EngineError>),
+    {
+        let next_game = AtomicU32::new(0);
+        let (sender, receiver) = mpsc::channel();
+        std::thread::scope(|scope| {
+            for _ in 0..self.threads.max(1) {
+                let (sender, next_game) = (sender.clone(), &next_game);
+                scope.spawn(move || {
+                    let mut engine = self.engine.create();
+                    loop {
+                        let game = next_game.fetch_add(1, Ordering::Relaxed);
+                        if game >= self.games {
+                            break;
+                        }
+                        let samples = self.play(engine.as_mut(), game);
+                        if sender.send((game, samples)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+
+            for (game, samples) in receiver {
+                report(game, samples);
+            }
+        });
+    }
+
+    /// Plays game number `game` and returns its positions.
+    pub fn play(&self, engine: &mut dyn Engine, game: u32) -> Result<Vec<Sample>, EngineError> {
+        let mut rng = StdRng::seed_from_u64(self.seed ^ (game as u64).wrapping_mul(0x9e37_79b9));
+        let mut board = match self.openings.len() {
+            0 => BitBoard::initial(),
+            n => self.openings[game as usize % n]
+                .iter()
+                .fold(BitBoard::initial(), |board, &position| board.play(position)),
+        };
+        for _ in 0..self.random_plies {
+            if board.is_game_ended() {
+                break;
+            }
+            let moves = board.moves();
+            board = board.play(moves.choose(&mut rng).copied().unwrap_or(0));
+        }
+
+        engine.new_game();
+        let mut samples = Vec::new();
+        while !board.is_game_ended() {
+            if board.legal_bits() == 0 {
+                board = board.play(0);
+                continue;
+            }
+            engine.set_position(board);
+            let mut info = SearchInfo::default();
+            let best_move = engine.think(self.limits, &mut |i| info = i.clone())?;
+            let visits: Vec<(u64, u32)> = info
+                .moves
+                .iter()
+                .map(|stat| (stat.position, stat.visits.min(u32::MAX as usize) as u32))
+                .collect();
+            let played = if samples.len() < self.temperature_plies && self.temperature > 0.0 {
+                let weights = visits
+                    .iter()
+                    .map(|&(_, n)| (n as f64).powf(1.0 / self.temperature));
+                match WeightedIndex::new(weights) {
+                    Ok(index) => visits[index.sample(&mut rng)].0,
+                    Err(_) => best_move,
+                }
+            } else {
+                best_move
+            };
+            samples.push(Sample {
+                board,
+                played,
+                value: info.score as f32,
+                result: 0,
+                visits,
+            });
+            board = board.play(played);
+        }
+
+        let final_score = board.final_score();
+        for sample in samples.iter_mut() {
+            let score = if sample.board.player() == board.player() {
+                final_score
+            } else {
+                -final_score
+            };
+            sample.result = score as i8;
+        }
+
+        Ok(samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_round_trip() {
+        let sample = Sample {
+            board: BitBoard::initial().play(1 << 26),
+            played: 0,
+            value: -0.25,
+            result: -12,
+            visits: vec![(1 << 18, 40), (1 << 20, 7)],
+        };
+        let mut writer = SampleWriter::new(Vec::new()).unwrap();
+        writer.write(&sample).unwrap();
+        writer.write(&sample).unwrap();
+        let bytes = writer.into_inner();
+
+        let samples: Vec<Sample> = SampleReader::new(&bytes[..])
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(samples, vec![sample.clone(), sample]);
+        let mut truncated = SampleReader::new(&bytes[..bytes.len() - 1]).unwrap();
+        assert!(truncated.next().unwrap().is_ok());
+        assert!(truncated.next().unwrap().is_err());
+        assert!(SampleReader::new(&b"OTSP\x09\x00\x00\x00"[..]).is_err());
+    }
+
+    #[test]
+    fn records_whole_games() {
+        let self_play = SelfPlay {
+            limits: Limits::iterations(30),
+            games: 3,
+            threads: 2,
+            random_plies: 2,
+            ..Default::default()
+        };
+        let mut games = Vec::new();
+        self_play.run(|game, samples| games.push((game, samples.unwrap())));
+        games.sort_by_key(|&(game, _)| game);
+        assert_eq!(games.len(), 3);
+        for (_, samples) in games.iter() {
+            let first = &samples[0];
+            assert_eq!(first.visits.iter().map(|&(_, n)| n).sum::<u32>(), 30);
+            for pair in samples.windows(2) {
+                let (a, b) = (&pair[0], &pair[1]);
+                let expected = if a.board.player() == b.board.player() {
+                    a.result
+                } else {
+                    -a.result
+                };
+                assert_eq!(b.result, expected);
+            }
+        }
+    }
+}
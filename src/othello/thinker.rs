@@ -0,0 +1,293 @@
+//! Searching for the AI's move without blocking the user interface.
+//!
+//! A `Thinker` owns an engine while it searches a position. Natively the
+//! search runs on a background thread; on the web, which has none, it
+//! advances a slice on every call to `step`, and engines that keep their
+//! search between calls (such as `MctsEngine`) pick up where they left off.
+//! `poll` hands the engine back with its move once the search is over.
+//! Natively an engine is lost if its search panics, or if `cancel` does not
+//! wait for it, and the caller creates a new one.
+
+use super::engine::{Engine, EngineError, Limits, SearchInfo};
+use super::moai::BitBoard;
+use wasm_timer::Instant;
+
+/// The engine back from a finished search, unless it panicked, and the move
+/// it chose.
+pub type Outcome = (Option<Box<dyn Engine>>, Result<u64, EngineError>);
+
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Thinker {
+    start: Instant,
+    info: std::sync::Arc<std::sync::Mutex<SearchInfo>>,
+    stop: super::engine::StopHandle,
+    /// Set by `move_now`, whose request is repeated until the search ends
+    /// in case it came before the search started.
+    moving_now: bool,
+    handle: Option<std::thread::JoinHandle<Outcome>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Thinker {
+    pub fn start(mut engine: Box<dyn Engine>, board: BitBoard, limits: Limits) -> Self {
+        let info = std::sync::Arc::new(std::sync::Mutex::new(SearchInfo::default()));
+        let stop = engine.stop_handle();
+        let progress = info.clone();
+        engine.set_position(board);
+        let handle = std::thread::spawn(move || {
+            let result = engine.think(limits, &mut |i| *progress.lock().unwrap() = i.clone());
+            (Some(engine), result)
+        });
+
+        Self {
+            start: Instant::now(),
+            info,
+            stop,
+            moving_now: false,
+            handle: Some(handle),
+        }
+    }
+
+    /// Does nothing: the search runs on its own thread.
+    pub fn step(&mut self, _time: u128) {}
+
+    /// The latest progress of the search.
+    pub fn info(&self) -> SearchInfo {
+        self.info.lock().unwrap().clone()
+    }
+
+    /// The engine and its move once the search is over.
+    pub fn poll(&mut self) -> Option<Outcome> {
+        if self.moving_now {
+            self.stop.stop();
+        }
+        if !self.handle.as_ref()?.is_finished() {
+            return None;
+        }
+
+        match self.handle.take().unwrap().join() {
+            Ok(outcome) => Some(outcome),
+            Err(panic) => {
+                let message = panic
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                let err = EngineError::Crashed(format!("search panicked: {}", message));
+                Some((None, Err(err)))
+            }
+        }
+    }
+
+    /// Stops the search and returns the engine, without its move. An engine
+    /// still searching is left to finish on its own thread rather than
+    /// waited for, and `None` is returned.
+    pub fn cancel(mut self) -> Option<Box<dyn Engine>> {
+        self.stop.stop();
+        let handle = self.handle.take()?;
+        if !handle.is_finished() {
+            return None;
+        }
+
+        handle.join().ok().and_then(|(engine, _)| engine)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub struct Thinker {
+    start: Instant,
+    engine: Box<dyn Engine>,
+    limits: Limits,
+    info: SearchInfo,
+    /// Simulations of the slices so far.
+    iterations: i32,
+    moving_now: bool,
+    /// The move of the last slice, kept once the search is over.
+    result: Option<Result<u64, EngineError>>,
+    done: bool,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Thinker {
+    pub fn start(mut engine: Box<dyn Engine>, board: BitBoard, limits: Limits) -> Self {
+        engine.set_position(board);
+        Self {
+            start: Instant::now(),
+            engine,
+            limits,
+            info: SearchInfo::default(),
+            iterations: 0,
+            moving_now: false,
+            result: None,
+            done: false,
+        }
+    }
+
+    /// Searches for about `time` milliseconds. Call it once per frame.
+    pub fn step(&mut self, time: u128) {
+        if self.done {
+            return;
+        }
+        let elapsed = self.start.elapsed().as_millis();
+        let time_left = self.limits.time.map(|total| total.saturating_sub(elapsed));
+        let iterations_left = self.limits.iterations.map(|n| n - self.iterations);
+        if self.result.is_some()
+            && (self.moving_now || time_left == Some(0) || iterations_left.is_some_and(|n| n <= 0))
+        {
+            self.done = true;
+            return;
+        }
+
+        let slice = Limits {
+            time: Some(time_left.map_or(time, |left| left.min(time))),
+            iterations: iterations_left,
+        };
+        let slice_start = Instant::now();
+        let mut info = SearchInfo::default();
+        let result = self.engine.think(slice, &mut |i| info = i.clone());
+        self.iterations += info.iterations;
+        self.info = SearchInfo {
+            iterations: self.iterations,
+            ..info
+        };
+        // Engines that do not use their time have found their move.
+        if result.is_err() || slice_start.elapsed().as_millis() < time / 2 {
+            self.done = true;
+        }
+        self.result = Some(result);
+    }
+
+    pub fn info(&self) -> SearchInfo {
+        self.info.clone()
+    }
+
+    pub fn poll(&mut self) -> Option<Outcome> {
+        if !self.done {
+            return None;
+        }
+        let result = self.result.take()?;
+        let engine = std::mem::replace(&mut self.engine, Box::new(Finished));
+        Some((Some(engine), result))
+    }
+
+    /// Stops the search and returns the engine, without its move.
+    pub fn cancel(self) -> Option<Box<dyn Engine>> {
+        Some(self.engine)
+    }
+}
+
+/// Stands in for the engine once `poll` has handed it back.
+#[cfg(target_arch = "wasm32")]
+struct Finished;
+
+#[cfg(target_arch = "wasm32")]
+impl Engine for Finished {
+    fn name(&self) -> String {
+        String::new()
+    }
+
+    fn set_position(&mut self, _board: BitBoard) {}
+
+    fn think(
+        &mut self,
+        _limits: Limits,
+        _info: &mut dyn FnMut(&SearchInfo),
+    ) -> Result<u64, EngineError> {
+        Err(EngineError::NoPosition)
+    }
+
+    fn stop_handle(&self) -> super::engine::StopHandle {
+        Default::default()
+    }
+}
+
+impl Thinker {
+    /// Milliseconds since the search started.
+    pub fn elapsed(&self) -> u128 {
+        self.start.elapsed().as_millis()
+    }
+
+    /// Makes the search end soon with the best move found so far.
+    pub fn move_now(&mut self) {
+        self.moving_now = true;
+        #[cfg(not(target_arch = "wasm32"))]
+        self.stop.stop();
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::othello::engine::EngineKind;
+    use std::time::Duration;
+
+    fn wait(thinker: &mut Thinker) -> Outcome {
+        loop {
+            if let Some(outcome) = thinker.poll() {
+                return outcome;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn thinks_in_the_background() {
+        let engine = EngineKind::Mcts(Default::default()).create();
+        let board = BitBoard::initial();
+        let mut thinker = Thinker::start(engine, board, Limits::iterations(200));
+        let (_, result) = wait(&mut thinker);
+        assert!(board.moves().contains(&result.unwrap()));
+
+        // Without limits the search only ends when asked to.
+        let engine = EngineKind::Mcts(Default::default()).create();
+        let mut thinker = Thinker::start(engine, board, Limits::default());
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(thinker.poll().is_none());
+        thinker.move_now();
+        let (engine, result) = wait(&mut thinker);
+        assert!(board.moves().contains(&result.unwrap()));
+
+        // Cancelling does not wait for the search to stop.
+        let thinker = Thinker::start(engine.unwrap(), board, Limits::default());
+        std::thread::sleep(Duration::from_millis(50));
+        let _ = thinker.cancel();
+    }
+
+    struct Panicking;
+
+    impl Engine for Panicking {
+        fn name(&self) -> String {
+            "panicking".to_owned()
+        }
+
+        fn set_position(&mut self, _board: BitBoard) {}
+
+        fn think(
+            &mut self,
+            _limits: Limits,
+            _info: &mut dyn FnMut(&SearchInfo),
+        ) -> Result<u64, EngineError> {
+            panic!("broken engine");
+        }
+
+        fn stop_handle(&self) -> crate::othello::engine::StopHandle {
+            Default::default()
+        }
+    }
+
+    #[test]
+    fn a_panicked_search_is_an_error() {
+        let mut thinker =
+            Thinker::start(Box::new(Panicking), BitBoard::initial(), Limits::default());
+        let (engine, result) = wait(&mut thinker);
+        assert!(engine.is_none());
+        assert_eq!(
+            result,
+            Err(EngineError::Crashed(
+                "search panicked: broken engine".to_owned()
+            ))
+        );
+        assert!(thinker.poll().is_none());
+        assert!(thinker.cancel().is_none());
+    }
+}
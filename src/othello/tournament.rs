@@ -0,0 +1,413 @@
+//! Round-robin and gauntlet tournaments between engines, with the
+//! statistics needed to tell whether a change made an engine stronger.
+
+use super::arena::{self, GameRecord, Score};
+use super::difficulty::elo_difference;
+use super::engine::{EngineKind, Limits};
+use super::game::{parse_square, Game};
+use super::moai::BitBoard;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+fn mirror_vertical(x: u64) -> u64 {
+    x.swap_bytes()
+}
+
+fn mirror_horizontal(mut x: u64) -> u64 {
+    x = ((x >> 1) & 0x5555_5555_5555_5555) | ((x & 0x5555_5555_5555_5555) << 1);
+    x = ((x >> 2) & 0x3333_3333_3333_3333) | ((x & 0x3333_3333_3333_3333) << 2);
+    ((x >> 4) & 0x0f0f_0f0f_0f0f_0f0f) | ((x & 0x0f0f_0f0f_0f0f_0f0f) << 4)
+}
+
+fn transpose(mut x: u64) -> u64 {
+    let mut t = 0x0f0f_0f0f_0000_0000 & (x ^ (x << 28));
+    x ^= t ^ (t >> 28);
+    t = 0x3333_0000_3333_0000 & (x ^ (x << 14));
+    x ^= t ^ (t >> 14);
+    t = 0x5500_5500_5500_5500 & (x ^ (x << 7));
+    x ^ t ^ (t >> 7)
+}
+
+/// The same position for all 8 symmetries of the board, as
+/// `(black, white, player)`; the smallest identifies the class.
+fn canonical(board: &BitBoard) -> (u64, u64, i32) {
+    let (black, white) = board.bits();
+    let mut best = (black, white, board.player());
+    for i in 0..8 {
+        let f = |mut x: u64| {
+            if i & 1 != 0 {
+                x = mirror_vertical(x);
+            }
+            if i & 2 != 0 {
+                x = mirror_horizontal(x);
+            }
+            if i & 4 != 0 {
+                x = transpose(x);
+            }
+            x
+        };
+        best = best.min((f(black), f(white), board.player()));
+    }
+
+    best
+}
+
+/// Every opening of `plies` moves from the initial position, keeping only
+/// one line per position up to symmetry. Colour reversal makes up for the
+/// imbalance of each opening, since both engines play both sides of it.
+pub fn openings(plies: usize) -> Vec<Vec<u64>> {
+    let mut seen = HashSet::new();
+    let mut lines = vec![Vec::new()];
+    for _ in 0..plies {
+        let mut next = Vec::new();
+        for line in lines {
+            let board = Game {
+                start: BitBoard::initial(),
+                moves: line,
+            };
+            for position in board.position().moves() {
+                let mut child = board.clone();
+                child.moves.push(position);
+                if seen.insert(canonical(&child.position())) {
+                    next.push(child.moves);
+                }
+            }
+        }
+        lines = next;
+    }
+
+    lines
+}
+
+/// Reads openings written one per line as moves such as `f5d6c3` or
+/// `f5 d6 c3`. Empty lines and lines starting with `#` are skipped.
+pub fn parse_openings(text: &str) -> Result<Vec<Vec<u64>>, String> {
+    let mut openings = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line: String = line.chars().filter(|ch| !ch.is_whitespace()).collect();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut game = Game::default();
+        for j in (0..line.len()).step_by(2) {
+            let name = line.get(j..j + 2).unwrap_or(&line[j..]);
+            let position =
+                parse_square(name).ok_or_else(|| format!("line {}: bad move {}", i + 1, name))?;
+            game.play(position)
+                .map_err(|err| format!("line {}: {}", i + 1, err))?;
+        }
+        openings.push(game.moves);
+    }
+    if openings.is_empty() {
+        return Err("no openings".to_owned());
+    }
+
+    Ok(openings)
+}
+
+/// Elo difference of a score and the half-width of its 95% confidence
+/// interval, from the variance of the game results.
+pub fn elo_with_margin(score: &Score) -> (f64, f64) {
+    let n = score.games() as f64;
+    let mean = score.ratio();
+    if n < 2.0 {
+        return (elo_difference(mean), f64::INFINITY);
+    }
+    let variance = (score.wins as f64 * (1.0 - mean).powi(2)
+        + score.draws as f64 * (0.5 - mean).powi(2)
+        + score.losses as f64 * mean.powi(2))
+        / n;
+    let margin = 1.96 * (variance / n).sqrt();
+    let (low, high) = (elo_difference(mean - margin), elo_difference(mean + margin));
+
+    (elo_difference(mean), (high - low) / 2.0)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SprtStatus {
+    Continue,
+    /// The Elo difference is most likely `elo0` or less.
+    AcceptH0,
+    /// The Elo difference is most likely `elo1` or more.
+    AcceptH1,
+}
+
+/// Sequential probability ratio test of H0: the Elo difference is `elo0`
+/// against H1: it is `elo1`, with the draw model of BayesElo as in other
+/// engine testing tools.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    /// False positive rate.
+    pub alpha: f64,
+    /// False negative rate.
+    pub beta: f64,
+}
+
+impl Sprt {
+    pub fn new(elo0: f64, elo1: f64) -> Self {
+        Self {
+            elo0,
+            elo1,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+
+    /// Log-likelihood ratio of H1 against H0. The draw rate is estimated
+    /// from the games, counting half a game for results that did not occur
+    /// so that one-sided matches can be decided too.
+    pub fn llr(&self, score: &Score) -> f64 {
+        if score.games() == 0 {
+            return 0.0;
+        }
+        let count = |n: u32| (n as f64).max(0.5);
+        let (wins, draws, losses) = (count(score.wins), count(score.draws), count(score.losses));
+        let n = wins + draws + losses;
+        let (w, l) = (wins / n, losses / n);
+        let draw_elo = 200.0 * f64::log10((1.0 - l) / l * (1.0 - w) / w);
+        let x = 10f64.powf(-draw_elo / 400.0);
+        let scale = 4.0 * x / (1.0 + x).powi(2);
+        let probabilities = |elo: f64| {
+            let elo = elo / scale;
+            let win = 1.0 / (1.0 + 10f64.powf((-elo + draw_elo) / 400.0));
+            let loss = 1.0 / (1.0 + 10f64.powf((elo + draw_elo) / 400.0));
+            (win, 1.0 - win - loss, loss)
+        };
+        let (w0, d0, l0) = probabilities(self.elo0);
+        let (w1, d1, l1) = probabilities(self.elo1);
+
+        score.wins as f64 * (w1 / w0).ln()
+            + score.draws as f64 * (d1 / d0).ln()
+            + score.losses as f64 * (l1 / l0).ln()
+    }
+
+    /// `(lower, upper)` LLR bounds at which H0 or H1 is accepted.
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    pub fn status(&self, score: &Score) -> SprtStatus {
+        let llr = self.llr(score);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            SprtStatus::AcceptH1
+        } else if llr <= lower {
+            SprtStatus::AcceptH0
+        } else {
+            SprtStatus::Continue
+        }
+    }
+}
+
+/// A finished game of a tournament.
+pub struct GameResult {
+    /// Index into `Tournament::pairs`.
+    pub pair: usize,
+    /// Indices into `Tournament::engines`.
+    pub black: usize,
+    pub white: usize,
+    pub opening: usize,
+    pub record: GameRecord,
+}
+
+/// Standing of a pair of engines, from the point of view of `a`.
+#[derive(Clone, Copy, Debug)]
+pub struct PairResult {
+    pub a: usize,
+    pub b: usize,
+    pub score: Score,
+    pub sprt: Option<SprtStatus>,
+}
+
+pub struct Tournament {
+    pub engines: Vec<EngineKind>,
+    /// Only the first engine plays the others, instead of everyone
+    /// playing everyone.
+    pub gauntlet: bool,
+    /// Games per pair. Each opening is played twice with colours reversed.
+    pub games: u32,
+    pub limits: Limits,
+    pub openings: Vec<Vec<u64>>,
+    pub threads: usize,
+    /// Stops playing a pair once the test is decided.
+    pub sprt: Option<Sprt>,
+}
+
+impl Tournament {
+    pub fn pairs(&self) -> Vec<(usize, usize)> {
+        let n = self.engines.len();
+        if self.gauntlet {
+            (1..n).map(|b| (0, b)).collect()
+        } else {
+            (0..n)
+                .flat_map(|a| (a + 1..n).map(move |b| (a, b)))
+                .collect()
+        }
+    }
+
+    /// Plays the tournament on `threads` threads, calling `report` with
+    /// every game and the updated standing of its pair as they finish.
+    pub fn run<F>(&self, mut report: F) -> Vec<PairResult>
+    where
+        F: FnMut(&GameResult, &PairResult),
+    {
+        let pairs = self.pairs();
+        let mut results: Vec<PairResult> = pairs
+            .iter()
+            .map(|&(a, b)| PairResult {
+                a,
+                b,
+                score: Score::default(),
+                sprt: self.sprt.map(|_| SprtStatus::Continue),
+            })
+            .collect();
+        // Games are interleaved across pairs so that all of them progress.
+        let jobs: Vec<(usize, u32)> = (0..self.games)
+            .flat_map(|game| (0..pairs.len()).map(move |pair| (pair, game)))
+            .collect();
+        let next_job = AtomicUsize::new(0);
+        let decided: Vec<AtomicBool> = pairs.iter().map(|_| AtomicBool::new(false)).collect();
+        let (sender, receiver) = mpsc::channel();
+
+        std::thread::scope(|scope| {
+            for _ in 0..self.threads.max(1) {
+                let sender = sender.clone();
+                let (pairs, jobs, next_job, decided) = (&pairs, &jobs, &next_job, &decided);
+                scope.spawn(move || {
+                    while let Some(&(pair, game)) =
+                        jobs.get(next_job.fetch_add(1, Ordering::Relaxed))
+                    {
+                        if decided[pair].load(Ordering::Relaxed) {
+                            continue;
+                        }
+                        let result = self.play(pair, pairs[pair], game);
+                        if sender.send(result).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+
+            for result in receiver {
+                let standing = &mut results[result.pair];
+                let player = if result.black == standing.a { 0 } else { 1 };
+                standing.score.add(&result.record, player);
+                if let Some(sprt) = self.sprt {
+                    let status = sprt.status(&standing.score);
+                    standing.sprt = Some(status);
+                    if status != SprtStatus::Continue {
+                        decided[result.pair].store(true, Ordering::Relaxed);
+                    }
+                }
+                report(&result, standing);
+            }
+        });
+
+        results
+    }
+
+    fn play(&self, pair: usize, (a, b): (usize, usize), game: u32) -> GameResult {
+        let opening = (game / 2) as usize % self.openings.len();
+        let start = self.openings[opening]
+            .iter()
+            .fold(BitBoard::initial(), |board, &position| board.play(position));
+        let (black, white) = if game.is_multiple_of(2) {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        let mut black_engine = self.engines[black].create();
+        let mut white_engine = self.engines[white].create();
+        let record = arena::play_engines(
+            start,
+            black_engine.as_mut(),
+            white_engine.as_mut(),
+            self.limits,
+        );
+
+        GameResult {
+            pair,
+            black,
+            white,
+            opening,
+            record,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn openings_are_unique_up_to_symmetry() {
+        // All first moves are equivalent; after two plies the reply is
+        // perpendicular, diagonal or parallel.
+        assert_eq!(openings(1).len(), 1);
+        assert_eq!(openings(2).len(), 3);
+        for x in [0x0123_4567_89ab_cdef, 1, 1 << 63] {
+            assert_eq!(transpose(transpose(x)), x);
+            assert_eq!(mirror_horizontal(mirror_horizontal(x)), x);
+        }
+    }
+
+    #[test]
+    fn parses_openings() {
+        let openings = parse_openings("# comment\nf5d6\n\nf5 f6 e6\n").unwrap();
+        assert_eq!(openings.len(), 2);
+        assert_eq!(openings[1].len(), 3);
+        assert!(parse_openings("f5f5").is_err());
+    }
+
+    #[test]
+    fn sprt_decides_lopsided_matches() {
+        let sprt = Sprt::new(0.0, 20.0);
+        let even = Score {
+            wins: 40,
+            draws: 20,
+            losses: 40,
+        };
+        let strong = Score {
+            wins: 300,
+            draws: 20,
+            losses: 100,
+        };
+        assert_eq!(sprt.status(&even), SprtStatus::Continue);
+        assert_eq!(sprt.status(&strong), SprtStatus::AcceptH1);
+        assert_eq!(
+            sprt.status(&Score {
+                wins: 100,
+                draws: 20,
+                losses: 300
+            }),
+            SprtStatus::AcceptH0
+        );
+        let (elo, margin) = elo_with_margin(&even);
+        assert!(elo.abs() < 1e-9 && margin > 0.0);
+    }
+
+    #[test]
+    fn runs_round_robins() {
+        let tournament = Tournament {
+            engines: vec![EngineKind::Random, EngineKind::Greedy, EngineKind::Corner],
+            gauntlet: false,
+            games: 4,
+            limits: Limits::default(),
+            openings: openings(2),
+            threads: 3,
+            sprt: None,
+        };
+        let mut games = 0;
+        let results = tournament.run(|_, _| games += 1);
+        assert_eq!(games, 12);
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|result| result.score.games() == 4));
+    }
+}
@@ -0,0 +1,449 @@
+//! Fitting the weights of `pattern::Evaluator` to recorded games, read from
+//! self-play files (see `selfplay`) or GGF game databases.
+//!
+//! Every position where a move was played is a sample of the disk
+//! difference the side to move ends with. Weights are fitted by stochastic
+//! gradient descent on the squared error against a `Target`, and a share of
+//! the games is held out to measure the error of each phase.
+
+use super::ggf::GgfGame;
+use super::moai::BitBoard;
+use super::pattern::{self, Evaluator, PHASES};
+use super::selfplay::{Sample, SampleReader, MAGIC};
+use rand::prelude::*;
+use std::io;
+
+/// The positions of a finished game where a move was played.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrainingGame {
+    pub positions: Vec<BitBoard>,
+    /// Final disk difference for black, see `BitBoard::final_score`.
+    pub result: i32,
+}
+
+impl TrainingGame {
+    fn result_for(&self, board: &BitBoard) -> i32 {
+        if board.player() == 0 {
+            self.result
+        } else {
+            -self.result
+        }
+    }
+}
+
+/// Splits a run of self-play samples into games: a sample continues the
+/// game of the previous one if its position is the one that move led to.
+pub fn games_from_samples(samples: &[Sample]) -> Vec<TrainingGame> {
+    let mut games: Vec<TrainingGame> = Vec::new();
+    let mut expected = None;
+    for sample in samples.iter() {
+        if expected != Some(sample.board) {
+            let result = sample.result as i32;
+            games.push(TrainingGame {
+                positions: Vec::new(),
+                result: if sample.board.player() == 0 {
+                    result
+                } else {
+                    -result
+                },
+            });
+        }
+        games.last_mut().unwrap().positions.push(sample.board);
+        // Self-play does not record passes.
+        let mut next = sample.board.play(sample.played);
+        if next.legal_bits() == 0 && !next.is_game_ended() {
+            next = next.play(0);
+        }
+        expected = Some(next);
+    }
+
+    games
+}
+
+/// The positions of a GGF game, or `None` if it did not end on the board,
+/// such as after a resignation.
+pub fn game_from_ggf(ggf: &GgfGame) -> Option<TrainingGame> {
+    let mut board = ggf.game.start;
+    let mut positions = Vec::new();
+    for &position in ggf.game.moves.iter() {
+        if position != 0 {
+            positions.push(board);
+        }
+        board = board.play(position);
+    }
+    if !board.is_game_ended() {
+        return None;
+    }
+    let score = board.final_score();
+
+    Some(TrainingGame {
+        positions,
+        result: if board.player() == 0 { score } else { -score },
+    })
+}
+
+/// Reads the games of a self-play file, or of a GGF file holding any
+/// number of games. GGF games that did not end on the board are left out.
+pub fn read_games(data: &[u8]) -> Result<Vec<TrainingGame>, String> {
+    if data.starts_with(MAGIC) {
+        let samples = SampleReader::new(data)
+            .and_then(|reader| reader.collect::<io::Result<Vec<_>>>())
+            .map_err(|err| err.to_string())?;
+        return Ok(games_from_samples(&samples));
+    }
+
+    let text = std::str::from_utf8(data).map_err(|_| "neither self-play nor GGF")?;
+    let mut games = Vec::new();
+    for (i, part) in text.split("(;").skip(1).enumerate() {
+        // Leave out whatever follows the game, up to the next one.
+        let end = part.rfind(";)").map_or(part.len(), |end| end + 2);
+        let ggf = GgfGame::parse(&format!("(;{}", &part[..end]))
+            .map_err(|err| format!("game {}: {}", i + 1, err))?;
+        games.extend(game_from_ggf(&ggf));
+    }
+
+    Ok(games)
+}
+
+/// What the evaluation of a position is fitted to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    /// The final result of the game.
+    Final,
+    /// The result with perfect play for positions with at most this many
+    /// empty squares, if the solver manages, and the final result for the
+    /// others.
+    Solved(u32),
+    /// TD(λ): the evaluations of the positions that followed, weighted by
+    /// powers of λ, and the final result. 1 is the same as `Final`, 0 is
+    /// the evaluation of the next position.
+    TdLambda(f32),
+}
+
+impl Target {
+    /// Parses `final`, `solve:<empties>` or `td:<lambda>`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let bad = || format!("bad target: {}", text);
+        let mut parts = text.splitn(2, ':');
+        match (parts.next().unwrap(), parts.next()) {
+            ("final", None) => Ok(Target::Final),
+            ("solve", Some(empties)) => Ok(Target::Solved(empties.parse().map_err(|_| bad())?)),
+            ("td", Some(lambda)) => match lambda.parse() {
+                Ok(lambda) if (0.0..=1.0).contains(&lambda) => Ok(Target::TdLambda(lambda)),
+                _ => Err(bad()),
+            },
+            _ => Err(bad()),
+        }
+    }
+}
+
+/// Errors of the evaluation, in disks, by phase.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PhaseErrors {
+    pub positions: [u64; PHASES],
+    /// Sums of the squared errors.
+    pub squared: [f64; PHASES],
+}
+
+impl PhaseErrors {
+    fn add(&mut self, board: &BitBoard, error: f32) {
+        let (black, white) = board.bits();
+        let phase = pattern::phase((!(black | white)).count_ones());
+        self.positions[phase] += 1;
+        self.squared[phase] += (error as f64).powi(2);
+    }
+
+    /// Root mean squared error of the positions in `phase`, or of all of
+    /// them for `None`.
+    pub fn rmse(&self, phase: Option<usize>) -> f64 {
+        let (positions, squared) = match phase {
+            Some(phase) => (self.positions[phase], self.squared[phase]),
+            None => (
+                self.positions.iter().sum(),
+                self.squared.iter().sum::<f64>(),
+            ),
+        };
+        if positions == 0 {
+            0.0
+        } else {
+            (squared / positions as f64).sqrt()
+        }
+    }
+}
+
+/// Progress after each pass over the training games.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EpochReport {
+    pub epoch: u32,
+    /// Errors against the training targets, before each update.
+    pub training: PhaseErrors,
+    /// Errors against the final or solved results of the held out games.
+    pub validation: PhaseErrors,
+}
+
+/// Settings of a training run.
+#[derive(Clone, Debug)]
+pub struct Trainer {
+    pub target: Target,
+    pub epochs: u32,
+    /// Share of the error corrected by each update, spread over the
+    /// weights of the position.
+    pub rate: f32,
+    /// Share of the games held out for validation.
+    pub validation: f64,
+    /// Positions the solver may search for each `Target::Solved` position.
+    pub solve_nodes: u64,
+    /// Threads solving positions.
+    pub threads: usize,
+    /// Decides which games are held out and the order of the updates.
+    pub seed: u64,
+}
+
+impl Default for Trainer {
+    fn default() -> Self {
+        Self {
+            target: Target::Final,
+            epochs: 10,
+            rate: 0.1,
+            validation: 0.1,
+            solve_nodes: 1_000_000,
+            threads: 1,
+            seed: 0,
+        }
+    }
+}
+
+impl Trainer {
+    /// Final or solved results of the positions of `game`, for the side to
+    /// move.
+    fn fixed_targets(&self, game: &TrainingGame) -> Vec<f32> {
+        game.positions
+            .iter()
+            .map(|board| {
+                let solved = match self.target {
+                    Target::Solved(empties)
+                        if 64 - board.count().0 - board.count().1 <= empties =>
+                    {
+                        board.solve(self.solve_nodes)
+                    }
+                    _ => None,
+                };
+                solved.map_or(game.result_for(board), |solution| solution.score) as f32
+            })
+            .collect()
+    }
+
+    /// `fixed_targets` of every game, shared among `threads`.
+    fn all_fixed_targets(&self, games: &[TrainingGame]) -> Vec<Vec<f32>> {
+        let chunk = (games.len() / self.threads.max(1)).max(1);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = games
+                .chunks(chunk)
+                .map(|games| {
+                    scope.spawn(move || {
+                        games
+                            .iter()
+                            .map(|game| self.fixed_targets(game))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        })
+    }
+
+    /// Fits `evaluator` to `games`, calling `report` after each epoch.
+    pub fn train<F>(&self, evaluator: &mut Evaluator, games: &[TrainingGame], mut report: F)
+    where
+        F: FnMut(&EpochReport),
+    {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut order: Vec<usize> = (0..games.len()).collect();
+        order.shuffle(&mut rng);
+        let held_out = (games.len() as f64 * self.validation).round() as usize;
+        let (validation, training) = order.split_at(held_out.min(games.len()));
+        let fixed = self.all_fixed_targets(games);
+
+        for epoch in 1..=self.epochs {
+            let targets: Vec<Vec<f32>> = match self.target {
+                Target::TdLambda(lambda) => games
+                    .iter()
+                    .map(|game| td_targets(evaluator, game, lambda))
+                    .collect(),
+                _ => fixed.clone(),
+            };
+            let mut samples: Vec<(usize, usize)> = training
+                .iter()
+                .flat_map(|&game| (0..games[game].positions.len()).map(move |ply| (game, ply)))
+                .collect();
+            samples.shuffle(&mut rng);
+
+            let mut errors = EpochReport {
+                epoch,
+                training: PhaseErrors::default(),
+                validation: PhaseErrors::default(),
+            };
+            for (game, ply) in samples {
+                let board = &games[game].positions[ply];
+                let (player, opponent) = board.curr_board();
+                let error = targets[game][ply] - evaluator.evaluate_bits(player, opponent);
+                errors.training.add(board, error);
+                let features = evaluator.features(player, opponent);
+                evaluator.update(&features, self.rate * error / features.len() as f32);
+            }
+            for &game in validation.iter() {
+                for (board, target) in games[game].positions.iter().zip(fixed[game].iter()) {
+                    errors
+                        .validation
+                        .add(board, target - evaluator.evaluate(board));
+                }
+            }
+            report(&errors);
+        }
+    }
+}
+
+/// TD(λ) targets of the positions of `game` for the side to move.
+fn td_targets(evaluator: &Evaluator, game: &TrainingGame, lambda: f32) -> Vec<f32> {
+    // Worked out from black's point of view, backwards from the end.
+    let black = |board: &BitBoard, value: f32| {
+        if board.player() == 0 {
+            value
+        } else {
+            -value
+        }
+    };
+    let mut targets = vec![0.0; game.positions.len()];
+    let mut target = game.result as f32;
+    for ply in (0..game.positions.len()).rev() {
+        if let Some(next) = game.positions.get(ply + 1) {
+            let value = evaluator.evaluate(next).clamp(-64.0, 64.0);
+            target = (1.0 - lambda) * black(next, value) + lambda * target;
+        }
+        let board = &game.positions[ply];
+        targets[ply] = black(board, target);
+    }
+
+    targets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::othello::engine::{EngineKind, Limits};
+    use crate::othello::selfplay::SelfPlay;
+
+    fn random_games(count: usize) -> Vec<TrainingGame> {
+        let mut rng = StdRng::seed_from_u64(3);
+        (0..count)
+            .map(|_| {
+                let mut board = BitBoard::initial();
+                let mut positions = Vec::new();
+                while !board.is_game_ended() {
+                    let moves = board.moves();
+                    if let Some(&position) = moves.choose(&mut rng) {
+                        positions.push(board);
+                        board = board.play(position);
+                    } else {
+                        board = board.play(0);
+                    }
+                }
+                let score = board.final_score();
+                TrainingGame {
+                    positions,
+                    result: if board.player() == 0 { score } else { -score },
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reads_games_from_samples_and_ggf() {
+        let self_play = SelfPlay {
+            engine: EngineKind::Random,
+            limits: Limits::iterations(1),
+            games: 3,
+            ..Default::default()
+        };
+        let mut samples = Vec::new();
+        self_play.run(|_, game| samples.extend(game.unwrap()));
+        let games = games_from_samples(&samples);
+        assert_eq!(games.len(), 3);
+        assert_eq!(
+            games.iter().map(|g| g.positions.len()).sum::<usize>(),
+            samples.len()
+        );
+        for game in games.iter() {
+            assert_eq!(game.positions[0], BitBoard::initial());
+        }
+
+        let text = "1 (;GM[Othello]PB[a]PW[b]TY[8]B[f5]W[d6];)\n\
+                    2 (;GM[Othello]PB[a]PW[b]TY[8]B[e6]W[f4]B[e3]W[f6]B[g5]W[d6]B[e7]W[f5]B[c5];)";
+        let games = read_games(text.as_bytes()).unwrap();
+        // Only the second game, a wipe-out, ended on the board.
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].positions.len(), 9);
+        assert_eq!(games[0].result, 64);
+        assert!(read_games(b"(;GM[Othello]B[zz];)").is_err());
+    }
+
+    #[test]
+    fn training_reduces_the_error() {
+        let games = random_games(300);
+        let trainer = Trainer {
+            epochs: 3,
+            validation: 0.2,
+            ..Default::default()
+        };
+        let mut evaluator = Evaluator::default();
+        let mut reports = Vec::new();
+        trainer.train(&mut evaluator, &games, |report| {
+            reports.push(report.clone())
+        });
+        assert_eq!(reports.len(), 3);
+        let (first, last) = (&reports[0], &reports[2]);
+        assert!(last.training.rmse(None) < first.training.rmse(None));
+        // Close to the end, results are easy to predict.
+        let untrained = {
+            let mut errors = PhaseErrors::default();
+            for game in games.iter() {
+                for board in game.positions.iter() {
+                    errors.add(board, game.result_for(board) as f32);
+                }
+            }
+            errors
+        };
+        assert!(last.validation.rmse(Some(0)) < untrained.rmse(Some(0)) * 0.8);
+
+        let td = Trainer {
+            target: Target::TdLambda(0.7),
+            epochs: 1,
+            ..Default::default()
+        };
+        td.train(&mut evaluator, &games, |_| {});
+        assert_eq!(Target::parse("td:0.7"), Ok(Target::TdLambda(0.7)));
+        assert_eq!(Target::parse("solve:12"), Ok(Target::Solved(12)));
+        assert!(Target::parse("td:2").is_err());
+    }
+
+    #[test]
+    fn solved_targets_are_exact() {
+        let games = random_games(1);
+        let trainer = Trainer {
+            target: Target::Solved(8),
+            ..Default::default()
+        };
+        let targets = trainer.fixed_targets(&games[0]);
+        for (board, target) in games[0].positions.iter().zip(targets) {
+            let (black, white) = board.count();
+            if 64 - black - white <= 8 {
+                assert_eq!(target, board.solve(u64::MAX).unwrap().score as f32);
+            } else {
+                assert_eq!(target, games[0].result_for(board) as f32);
+            }
+        }
+    }
+}